@@ -1,5 +1,15 @@
-mod domain_type;
+pub mod chat_client;
+pub mod chat_messenger;
+pub mod dns_checker;
 pub mod geoip;
 pub mod http_checker;
+pub mod info_page;
+pub mod metrics;
+pub mod resolver;
 pub mod servers_checker;
 pub mod servers_repository;
+pub mod smp_handshake;
+pub mod socks;
+pub mod tls_certificate;
+pub mod transport_checker;
+pub mod webhook;
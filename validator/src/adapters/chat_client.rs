@@ -0,0 +1,32 @@
+use std::net::TcpStream;
+use std::time::Duration;
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+/// WebSocket connection to the simplex-chat client
+pub type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+pub async fn send_command(socket: &mut Socket, cmd: String) -> Option<serde_json::Value> {
+    let corr_id = rand::random::<u32>().to_string();
+
+    let message = serde_json::json!({
+        "corrId": corr_id,
+        "cmd": cmd
+    });
+
+    socket
+        .send(Message::Text(message.to_string().into()))
+        .ok()?;
+
+    while let Ok(msg) = socket.read() {
+        if let Message::Text(text) = msg {
+            if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
+                if response["corrId"] == corr_id {
+                    return Some(response);
+                }
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    None
+}
@@ -0,0 +1,235 @@
+use crate::{
+    adapters::chat_client::{send_command, Socket},
+    validator::{
+        events::{OPT_IN_COMMAND, OPT_OUT_COMMAND},
+        ports::{MessageDelivery, OperatorMessengerPort},
+    },
+};
+use std::error::Error;
+use tracing::info;
+use tungstenite::connect;
+
+/// Active user of the chat client the messages are sent from
+const USER_ID: u32 = 1;
+/// Chat items searched for the operator's last opt-out or opt-in reply
+const HISTORY_SIZE: u32 = 100;
+
+pub struct ChatMessengerConfiguration {
+    /// WebSocket of the simplex-chat client, the same one servers are tested with
+    pub chat_client_uri: String,
+    /// Logs the messages instead of sending them
+    pub is_dry: bool,
+}
+
+pub struct ChatMessenger {
+    config: ChatMessengerConfiguration,
+}
+
+fn is_error(response: &serde_json::Value) -> bool {
+    let type_ = &response["resp"]["type"];
+    type_ == "chatCmdError" || type_ == "chatError"
+}
+
+/// How the chat client relates to a contact address
+#[derive(Debug, PartialEq, Eq)]
+enum AddressPlan {
+    Known {
+        contact_id: i64,
+    },
+    Unknown,
+    /// A request sent earlier hasn't been accepted yet
+    Pending,
+}
+
+fn parse_address_plan(response: &serde_json::Value) -> Result<AddressPlan, Box<dyn Error>> {
+    let address_plan = &response["resp"]["connectionPlan"]["contactAddressPlan"];
+    match address_plan["type"].as_str() {
+        Some("known") => Ok(AddressPlan::Known {
+            contact_id: address_plan["contact"]["contactId"]
+                .as_i64()
+                .ok_or("Known contact without an id")?,
+        }),
+        Some("ok") => Ok(AddressPlan::Unknown),
+        Some("connectingProhibit") | Some("connectingConfirmReconnect") => Ok(AddressPlan::Pending),
+        _ => Err(format!("Can't message contact address: {}", response["resp"]).into()),
+    }
+}
+
+/// Whether the last opt-out or opt-in command the operator sent in the chat is the opt-out
+fn has_opted_out(response: &serde_json::Value) -> bool {
+    let Some(items) = response["resp"]["chat"]["chatItems"].as_array() else {
+        return false;
+    };
+    // items are ordered from the oldest
+    items
+        .iter()
+        .rev()
+        .filter(|item| item["chatDir"]["type"] == "directRcv")
+        .filter_map(|item| item["content"]["msgContent"]["text"].as_str())
+        .map(str::trim)
+        .find_map(|text| {
+            if text.eq_ignore_ascii_case(OPT_OUT_COMMAND) {
+                Some(true)
+            } else if text.eq_ignore_ascii_case(OPT_IN_COMMAND) {
+                Some(false)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(false)
+}
+
+impl ChatMessenger {
+    pub fn new(config: ChatMessengerConfiguration) -> Self {
+        Self { config }
+    }
+
+    async fn command(
+        &self,
+        socket: &mut Socket,
+        cmd: String,
+    ) -> Result<serde_json::Value, Box<dyn Error>> {
+        let response = send_command(socket, cmd)
+            .await
+            .ok_or("No response from the chat client")?;
+        if is_error(&response) {
+            return Err(format!("Chat command failed: {}", response["resp"]).into());
+        }
+        Ok(response)
+    }
+
+    async fn _send_message(
+        &self,
+        contact_address: &str,
+        text: &str,
+        may_request_contact: bool,
+    ) -> Result<MessageDelivery, Box<dyn Error>> {
+        if self.config.is_dry {
+            info!(contact_address, text, "Dry run: would send a message");
+            return Ok(MessageDelivery::Sent);
+        }
+        let (mut socket, _response) = connect(&self.config.chat_client_uri)?;
+        let plan = self
+            .command(
+                &mut socket,
+                format!("/_connect plan {} {}", USER_ID, contact_address.trim()),
+            )
+            .await?;
+        match parse_address_plan(&plan)? {
+            AddressPlan::Known { contact_id } => {
+                let history = self
+                    .command(
+                        &mut socket,
+                        format!("/_get chat @{} count={}", contact_id, HISTORY_SIZE),
+                    )
+                    .await?;
+                if has_opted_out(&history) {
+                    return Ok(MessageDelivery::OptedOut);
+                }
+                let message = serde_json::json!([{"msgContent": {"type": "text", "text": text}}]);
+                self.command(
+                    &mut socket,
+                    format!("/_send @{} json {}", contact_id, message),
+                )
+                .await?;
+                Ok(MessageDelivery::Sent)
+            }
+            AddressPlan::Unknown if may_request_contact => {
+                self.command(
+                    &mut socket,
+                    format!("/_connect {} {}", USER_ID, contact_address.trim()),
+                )
+                .await?;
+                Ok(MessageDelivery::ContactRequested)
+            }
+            AddressPlan::Unknown | AddressPlan::Pending => Ok(MessageDelivery::ContactPending),
+        }
+    }
+}
+
+impl OperatorMessengerPort for ChatMessenger {
+    async fn send_message(
+        &self,
+        contact_address: &str,
+        text: &str,
+        may_request_contact: bool,
+    ) -> Option<MessageDelivery> {
+        self._send_message(contact_address, text, may_request_contact)
+            .await
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn plan(address_plan: serde_json::Value) -> serde_json::Value {
+        json!({"resp": {"type": "connectionPlan", "connectionPlan": {"type": "contactAddress", "contactAddressPlan": address_plan}}})
+    }
+
+    fn received(text: &str) -> serde_json::Value {
+        json!({"chatDir": {"type": "directRcv"}, "content": {"type": "rcvMsgContent", "msgContent": {"type": "text", "text": text}}})
+    }
+
+    fn sent(text: &str) -> serde_json::Value {
+        json!({"chatDir": {"type": "directSnd"}, "content": {"type": "sndMsgContent", "msgContent": {"type": "text", "text": text}}})
+    }
+
+    fn history(items: Vec<serde_json::Value>) -> serde_json::Value {
+        json!({"resp": {"type": "apiChat", "chat": {"chatItems": items}}})
+    }
+
+    #[test]
+    fn test_address_plan() {
+        assert_eq!(
+            parse_address_plan(&plan(json!({"type": "known", "contact": {"contactId": 7}})))
+                .expect("Known contact"),
+            AddressPlan::Known { contact_id: 7 }
+        );
+        assert_eq!(
+            parse_address_plan(&plan(json!({"type": "ok"}))).expect("Unknown contact"),
+            AddressPlan::Unknown
+        );
+        assert_eq!(
+            parse_address_plan(&plan(json!({"type": "connectingProhibit"})))
+                .expect("Pending request"),
+            AddressPlan::Pending
+        );
+        assert!(parse_address_plan(&plan(json!({"type": "ownLink"}))).is_err());
+        assert!(parse_address_plan(&plan(json!({"type": "known", "contact": {}}))).is_err());
+    }
+
+    #[test]
+    fn test_opt_out() {
+        assert!(!has_opted_out(&history(vec![])));
+        assert!(!has_opted_out(&json!({"resp": {"type": "chatCmdError"}})));
+        assert!(has_opted_out(&history(vec![
+            received("thanks"),
+            received(" /STOP "),
+            sent("Your server failed"),
+        ])));
+        assert!(!has_opted_out(&history(vec![
+            received("/stop"),
+            received("/start"),
+        ])));
+        // our own messages can't opt the operator out
+        assert!(!has_opted_out(&history(vec![sent("/stop")])));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let messenger = ChatMessenger::new(ChatMessengerConfiguration {
+            chat_client_uri: "ws://127.0.0.1:1".to_string(),
+            is_dry: true,
+        });
+        assert_eq!(
+            messenger
+                ._send_message("simplex:/contact#/?v=2", "text", true)
+                .await
+                .expect("Nothing is sent in a dry run"),
+            MessageDelivery::Sent
+        );
+    }
+}
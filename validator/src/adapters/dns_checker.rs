@@ -0,0 +1,123 @@
+use crate::validator::{
+    domain_type::{parse_origin, Type},
+    ports::{AddressFamily, DnsCheckerPort, DnsStatus, ResolverPort},
+};
+use std::net::IpAddr;
+
+pub struct DnsChecker<R: ResolverPort> {
+    resolver: R,
+}
+
+impl<R: ResolverPort> DnsChecker<R> {
+    pub fn new(resolver: R) -> Self {
+        Self { resolver }
+    }
+}
+
+impl<R: ResolverPort> DnsCheckerPort for DnsChecker<R> {
+    async fn check_dns(&self, host: &str) -> Option<DnsStatus> {
+        let host_info = parse_origin(host).ok()?;
+        if !matches!(host_info.domain_type, Type::Clearnet)
+            || host_info.value.parse::<IpAddr>().is_ok()
+        {
+            return None;
+        }
+
+        let (v4, v6) = tokio::join!(
+            self.resolver.lookup(&host_info.value, AddressFamily::V4),
+            self.resolver.lookup(&host_info.value, AddressFamily::V6)
+        );
+        // NXDOMAIN and empty answers are empty lookups; a timeout or SERVFAIL of either family
+        // leaves the status unknown rather than reporting the missing records as a DNS change
+        let (v4, v6) = (v4?, v6?);
+
+        let has_a = !v4.addresses.is_empty();
+        let has_aaaa = !v6.addresses.is_empty();
+        let cname_chain = if v4.cname_chain.is_empty() {
+            v6.cname_chain
+        } else {
+            v4.cname_chain
+        };
+        let mut addresses: Vec<IpAddr> = v4.addresses.into_iter().chain(v6.addresses).collect();
+        addresses.sort();
+        addresses.dedup();
+
+        Some(DnsStatus {
+            resolves: has_a || has_aaaa,
+            has_a,
+            has_aaaa,
+            cname_chain,
+            ttl: v4.ttl.into_iter().chain(v6.ttl).min(),
+            addresses,
+            changed: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator::ports::DnsLookup;
+
+    /// Answers A queries with `v4` and AAAA queries with `v6`; `None` is a failed lookup
+    struct FakeResolver {
+        v4: Option<Vec<IpAddr>>,
+        v6: Option<Vec<IpAddr>>,
+    }
+
+    impl ResolverPort for FakeResolver {
+        async fn resolve(&self, _host: &str) -> Option<Vec<IpAddr>> {
+            None
+        }
+
+        async fn lookup(&self, _host: &str, family: AddressFamily) -> Option<DnsLookup> {
+            let addresses = match family {
+                AddressFamily::V4 => self.v4.clone(),
+                AddressFamily::V6 => self.v6.clone(),
+            }?;
+            Some(DnsLookup {
+                addresses,
+                cname_chain: vec![],
+                ttl: Some(300),
+            })
+        }
+    }
+
+    async fn check(v4: Option<Vec<IpAddr>>, v6: Option<Vec<IpAddr>>) -> Option<DnsStatus> {
+        DnsChecker::new(FakeResolver { v4, v6 })
+            .check_dns("smp.example.com")
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_resolves() {
+        let address: IpAddr = "203.0.113.1".parse().expect("valid address");
+        let status = check(Some(vec![address]), Some(vec![]))
+            .await
+            .expect("status");
+        assert!(status.resolves && status.has_a && !status.has_aaaa);
+        assert_eq!(status.addresses, [address]);
+    }
+
+    #[tokio::test]
+    async fn test_nxdomain_does_not_resolve() {
+        let status = check(Some(vec![]), Some(vec![])).await.expect("status");
+        assert!(!status.resolves);
+    }
+
+    #[tokio::test]
+    async fn test_failed_lookup_is_unknown() {
+        let address: IpAddr = "203.0.113.1".parse().expect("valid address");
+        assert!(check(None, None).await.is_none());
+        assert!(check(Some(vec![address]), None).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_skips_hosts_without_dns() {
+        let checker = DnsChecker::new(FakeResolver {
+            v4: Some(vec![]),
+            v6: Some(vec![]),
+        });
+        assert!(checker.check_dns("203.0.113.1").await.is_none());
+    }
+}
@@ -1,20 +1,46 @@
-use maxminddb;
+mod csv_ranges;
+mod mmdb;
+mod overrides;
+
 use std::error::Error;
-use std::net::ToSocketAddrs;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{error, warn};
 
-use crate::adapters::domain_type::{parse_origin, Type};
-use crate::validator::ports::GeoIpPort;
+use crate::validator::domain_type::{parse_origin, Type};
+use crate::validator::ports::{GeoIpPort, GeoLocation, ResolverPort};
 
-pub type GeoIpClient = maxminddb::Reader<Vec<u8>>;
+pub use csv_ranges::CsvRangesConfiguration;
+pub use mmdb::{DatabaseUpdate, MmdbConfiguration};
 
-fn resolve(domain: &str) -> Result<IpAddr, Box<dyn Error>> {
-    let mut addrs = (domain, 0).to_socket_addrs()?;
-    if let Some(addr) = addrs.next() {
-        Ok(addr.ip())
-    } else {
-        Err("No valid IP address found".into())
+const OVERRIDES_SOURCE: &str = "override";
+
+/// Sources are tried in order: overrides by host, then mmdb files, then CSV range files
+pub struct GeoIpConfiguration {
+    pub overrides_path: Option<String>,
+    pub mmdb: Vec<MmdbConfiguration>,
+    pub csv_ranges: Vec<CsvRangesConfiguration>,
+}
+
+enum Source {
+    Mmdb(mmdb::Mmdb),
+    CsvRanges(csv_ranges::CsvRanges),
+}
+
+impl Source {
+    fn name(&self) -> &str {
+        match self {
+            Source::Mmdb(mmdb) => mmdb.name(),
+            Source::CsvRanges(csv_ranges) => csv_ranges.name(),
+        }
+    }
+
+    fn get_country(&self, ip: IpAddr) -> Result<Option<String>, Box<dyn Error>> {
+        match self {
+            Source::Mmdb(mmdb) => mmdb.get_country(ip),
+            Source::CsvRanges(csv_ranges) => Ok(csv_ranges.get_country(ip)),
+        }
     }
 }
 
@@ -40,46 +66,107 @@ fn str_to_ip(ip: &str) -> Result<IpAddr, Box<dyn Error>> {
     }
 }
 
-pub struct GeoIp {
-    reader: GeoIpClient,
+pub struct GeoIp<R: ResolverPort> {
+    overrides: Option<overrides::Overrides>,
+    sources: Vec<Source>,
+    resolver: Arc<R>,
 }
 
-impl GeoIp {
-    pub fn new(path: &str) -> Result<Self, Box<dyn Error>> {
+impl<R: ResolverPort + Send + Sync + 'static> GeoIp<R> {
+    pub fn new(config: GeoIpConfiguration, resolver: R) -> Result<Self, Box<dyn Error>> {
+        let resolver = Arc::new(resolver);
+        let overrides = config
+            .overrides_path
+            .map(|path| overrides::Overrides::new(&path))
+            .transpose()?;
+        let mut sources = vec![];
+        for mmdb in config.mmdb {
+            sources.push(Source::Mmdb(mmdb::Mmdb::new(mmdb, &resolver)?));
+        }
+        for csv_ranges in config.csv_ranges {
+            sources.push(Source::CsvRanges(csv_ranges::CsvRanges::new(csv_ranges)?));
+        }
+
         Ok(Self {
-            reader: maxminddb::Reader::open_readfile(path)?,
+            overrides,
+            sources,
+            resolver,
         })
     }
 
-    fn _get_country(&self, authority: &str) -> Result<String, Box<dyn Error>> {
-        let host = parse_origin(authority);
+    /// Refreshes stale mmdb databases. Fails if any of them refuses to be used while stale.
+    async fn _ensure_fresh(&self) -> Result<(), Box<dyn Error>> {
+        for source in &self.sources {
+            if let Source::Mmdb(mmdb) = source {
+                mmdb.ensure_fresh().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn _get_country(&self, authority: &str) -> Result<GeoLocation, Box<dyn Error>> {
+        let host = parse_origin(authority)?;
 
         match host.domain_type {
-            Type::Onion => Ok("TOR".to_string()),
-            Type::Yggdrasil => Ok("YGGDRASIL".to_string()),
+            Type::Onion | Type::I2p | Type::Yggdrasil => {
+                Err("Overlay network hosts cannot be geolocated".into())
+            }
             Type::Clearnet => {
+                if let Some(country) = self
+                    .overrides
+                    .as_ref()
+                    .and_then(|overrides| overrides.get_country(&host.value))
+                {
+                    return Ok(GeoLocation {
+                        country,
+                        source: OVERRIDES_SOURCE.to_string(),
+                    });
+                }
+
                 let ip: IpAddr = if is_ip_address(host.value.as_str()) {
                     str_to_ip(host.value.as_str())?
                 } else {
-                    resolve(host.value.as_str())?
+                    *self
+                        .resolver
+                        .resolve(host.value.as_str())
+                        .await
+                        .ok_or("Cannot resolve host")?
+                        .first()
+                        .ok_or("No valid IP address found")?
                 };
 
-                let result = self.reader.lookup(ip)?;
-                let country: maxminddb::geoip2::Country = result
-                    .decode()?
-                    .ok_or("Country information could not be found")?;
-
-                match country.country.iso_code {
-                    Some(code) => Ok(code.to_string()),
-                    None => Err("No country code found".into()),
+                for source in &self.sources {
+                    match source.get_country(ip) {
+                        Ok(Some(country)) => {
+                            return Ok(GeoLocation {
+                                country,
+                                source: source.name().to_string(),
+                            })
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!(
+                            source = source.name(),
+                            %ip,
+                            error = %e,
+                            "GeoIP source failed"
+                        ),
+                    }
                 }
+                Err("No country code found".into())
             }
         }
     }
 }
 
-impl GeoIpPort for GeoIp {
-    async fn get_country(&self, host: &str) -> Option<String> {
-        self._get_country(host).ok()
+impl<R: ResolverPort + Send + Sync + 'static> GeoIpPort for GeoIp<R> {
+    async fn ensure_fresh(&self) -> Option<()> {
+        self._ensure_fresh()
+            .await
+            .inspect_err(|e| error!(error = %e, "Cannot use GeoIP database"))
+            .ok()
+    }
+
+    async fn get_country(&self, host: &str) -> Option<GeoLocation> {
+        self._get_country(host).await.ok()
     }
 }
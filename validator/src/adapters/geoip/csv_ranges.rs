@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// IP range file with `start,end,country` lines. Addresses may be written as IPs (DB-IP) or as
+/// quoted integers (IP2Location); extra columns are ignored.
+pub struct CsvRangesConfiguration {
+    pub name: String,
+    pub path: String,
+}
+
+struct Range<T> {
+    start: T,
+    end: T,
+    country: String,
+}
+
+pub struct CsvRanges {
+    name: String,
+    v4: Vec<Range<u32>>,
+    v6: Vec<Range<u128>>,
+}
+
+fn parse_address(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    let number = value.parse::<u128>().ok()?;
+    match u32::try_from(number) {
+        Ok(v4) => Some(IpAddr::V4(Ipv4Addr::from(v4))),
+        Err(_) => Some(IpAddr::V6(Ipv6Addr::from(number))),
+    }
+}
+
+fn find<T: Ord + Copy>(ranges: &[Range<T>], value: T) -> Option<&str> {
+    let index = ranges.partition_point(|range| range.start <= value);
+    let range = ranges.get(index.checked_sub(1)?)?;
+    (value <= range.end).then_some(range.country.as_str())
+}
+
+impl CsvRanges {
+    pub fn new(config: CsvRangesConfiguration) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(&config.path)?;
+        Self::parse(config.name, &content)
+    }
+
+    fn parse(name: String, content: &str) -> Result<Self, Box<dyn Error>> {
+        let mut v4 = vec![];
+        let mut v6 = vec![];
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut columns = line.split(',');
+            let (Some(start), Some(end), Some(country)) =
+                (columns.next(), columns.next(), columns.next())
+            else {
+                return Err(format!("Invalid line {} in {}", number + 1, name).into());
+            };
+            let country = country.trim().trim_matches('"').to_uppercase();
+            // IP2Location marks unallocated ranges with "-"
+            if country.len() != 2 {
+                continue;
+            }
+
+            match (parse_address(start), parse_address(end)) {
+                (Some(IpAddr::V4(start)), Some(IpAddr::V4(end))) => v4.push(Range {
+                    start: u32::from(start),
+                    end: u32::from(end),
+                    country,
+                }),
+                (Some(start), Some(end)) => v6.push(Range {
+                    start: u128::from(to_ipv6(start)),
+                    end: u128::from(to_ipv6(end)),
+                    country,
+                }),
+                _ => return Err(format!("Invalid range at line {} in {}", number + 1, name).into()),
+            }
+        }
+        v4.sort_by_key(|range| range.start);
+        v6.sort_by_key(|range| range.start);
+
+        Ok(Self { name, v4, v6 })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_country(&self, ip: IpAddr) -> Option<String> {
+        match ip {
+            IpAddr::V4(v4) => find(&self.v4, u32::from(v4)),
+            IpAddr::V6(v6) => find(&self.v6, u128::from(v6)),
+        }
+        .map(|country| country.to_string())
+    }
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(content: &str) -> CsvRanges {
+        CsvRanges::parse("test".to_string(), content).expect("valid ranges")
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().expect("valid address")
+    }
+
+    #[test]
+    fn test_dbip_format() {
+        let r = ranges("1.0.0.0,1.0.0.255,AU\n1.0.1.0,1.0.3.255,CN\n2001:200::,2001:200:ffff:ffff:ffff:ffff:ffff:ffff,JP\n");
+        assert_eq!(r.get_country(ip("1.0.0.7")), Some("AU".to_string()));
+        assert_eq!(r.get_country(ip("1.0.2.1")), Some("CN".to_string()));
+        assert_eq!(r.get_country(ip("2001:200::1")), Some("JP".to_string()));
+    }
+
+    #[test]
+    fn test_ip2location_format() {
+        let r = ranges("\"0\",\"16777215\",\"-\",\"-\"\n\"16777216\",\"16777471\",\"US\",\"United States of America\"\n");
+        assert_eq!(r.get_country(ip("1.0.0.1")), Some("US".to_string()));
+        assert_eq!(r.get_country(ip("0.0.0.1")), None);
+    }
+
+    #[test]
+    fn test_address_outside_ranges() {
+        let r = ranges("1.0.0.0,1.0.0.255,AU\n1.0.2.0,1.0.2.255,CN\n");
+        assert_eq!(r.get_country(ip("1.0.1.1")), None);
+        assert_eq!(r.get_country(ip("0.255.255.255")), None);
+        assert_eq!(r.get_country(ip("1.0.3.0")), None);
+    }
+
+    #[test]
+    fn test_invalid_line() {
+        assert!(CsvRanges::parse("test".to_string(), "1.0.0.0,AU\n").is_err());
+        assert!(CsvRanges::parse("test".to_string(), "foo,bar,AU\n").is_err());
+    }
+}
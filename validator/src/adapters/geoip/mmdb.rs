@@ -0,0 +1,321 @@
+use crate::adapters::resolver::PublicResolver;
+use crate::validator::ports::ResolverPort;
+use maxminddb;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+pub type GeoIpClient = maxminddb::Reader<Vec<u8>>;
+
+pub struct DatabaseUpdate {
+    pub url: String,
+    /// URL of a file whose first token is the hex SHA-256 of the database
+    pub sha256_url: Option<String>,
+    pub cache_dir: PathBuf,
+    /// Downloads through this proxy (e.g. Tor) instead of connecting directly
+    pub proxy: Option<String>,
+    pub timeout: Duration,
+    /// Larger downloads are abandoned before they are held in memory
+    pub max_size: usize,
+}
+
+/// MaxMind DB file; DB-IP and IP2Location publish databases in the same format
+pub struct MmdbConfiguration {
+    pub name: String,
+    pub path: String,
+    pub max_age: Option<Duration>,
+    pub refuse_stale: bool,
+    pub update: Option<DatabaseUpdate>,
+}
+
+fn get_database_age(reader: &GeoIpClient) -> Duration {
+    get_age(reader.metadata().build_epoch, SystemTime::now())
+}
+
+/// Time since the Unix timestamp a database was built at; zero for future build dates
+fn get_age(build_epoch: u64, now: SystemTime) -> Duration {
+    let built_at = UNIX_EPOCH + Duration::from_secs(build_epoch);
+    now.duration_since(built_at).unwrap_or(Duration::ZERO)
+}
+
+/// Checks `database` against the first token of a checksum file such as `sha256sum` writes
+fn verify_checksum(database: &[u8], checksum_file: &str) -> Result<(), Box<dyn Error>> {
+    let expected = checksum_file
+        .split_whitespace()
+        .next()
+        .ok_or("Checksum file is empty")?
+        .to_lowercase();
+    let actual = format!("{:x}", Sha256::digest(database));
+    if expected != actual {
+        return Err(format!("Checksum mismatch: expected {}, got {}", expected, actual).into());
+    }
+    Ok(())
+}
+
+/// Reads the body of a successful response to `url`, failing once it exceeds `max_size`
+async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    max_size: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_size as u64)
+    {
+        return Err(format!("{} is larger than {} bytes", url, max_size).into());
+    }
+    let mut body: Vec<u8> = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_size {
+            return Err(format!("{} is larger than {} bytes", url, max_size).into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Stores `database` at `cached_path` through `temporary_path` if it is newer than
+/// `current_epoch`; the temporary file is removed if that fails at any step
+async fn install_database(
+    database: Vec<u8>,
+    current_epoch: u64,
+    temporary_path: &Path,
+    cached_path: &Path,
+) -> Result<GeoIpClient, Box<dyn Error>> {
+    let result = async {
+        tokio::fs::write(temporary_path, &database).await?;
+        let reader = maxminddb::Reader::from_source(database)?;
+        if reader.metadata().build_epoch <= current_epoch {
+            return Err("Downloaded database is not newer than the current one".into());
+        }
+        tokio::fs::rename(temporary_path, cached_path).await?;
+        Ok(reader)
+    }
+    .await;
+    if result.is_err() && tokio::fs::try_exists(temporary_path).await.unwrap_or(false) {
+        if let Err(e) = tokio::fs::remove_file(temporary_path).await {
+            warn!(path = %temporary_path.display(), error = %e, "Cannot remove the temporary file");
+        }
+    }
+    result
+}
+
+/// Where and how a database is refreshed
+struct Updater {
+    config: DatabaseUpdate,
+    client: reqwest::Client,
+}
+
+pub struct Mmdb {
+    name: String,
+    reader: RwLock<Arc<GeoIpClient>>,
+    max_age: Option<Duration>,
+    refuse_stale: bool,
+    updater: Option<Updater>,
+}
+
+impl Mmdb {
+    /// Downloads resolve names through `resolver`
+    pub fn new<R: ResolverPort + Send + Sync + 'static>(
+        config: MmdbConfiguration,
+        resolver: &Arc<R>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut reader = maxminddb::Reader::open_readfile(&config.path)?;
+
+        // a database refreshed by a previous run is preferred over the bundled one if it is newer
+        if let Some(update) = &config.update {
+            let cached_path = update.cache_dir.join(format!("{}.mmdb", config.name));
+            if cached_path.exists() {
+                match maxminddb::Reader::open_readfile(&cached_path) {
+                    Ok(cached) if cached.metadata().build_epoch > reader.metadata().build_epoch => {
+                        info!(path = %cached_path.display(), "Using cached GeoIP database");
+                        reader = cached;
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!(
+                        path = %cached_path.display(),
+                        error = %e,
+                        "Cannot open cached GeoIP database"
+                    ),
+                }
+            }
+        }
+
+        let updater = config
+            .update
+            .map(|update| -> Result<Updater, Box<dyn Error>> {
+                let mut builder = reqwest::Client::builder()
+                    .dns_resolver(Arc::new(PublicResolver(resolver.clone())))
+                    .timeout(update.timeout);
+                if let Some(proxy) = &update.proxy {
+                    builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+                }
+                Ok(Updater {
+                    client: builder.build()?,
+                    config: update,
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            name: config.name,
+            reader: RwLock::new(Arc::new(reader)),
+            max_age: config.max_age,
+            refuse_stale: config.refuse_stale,
+            updater,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_reader(&self) -> Result<Arc<GeoIpClient>, Box<dyn Error>> {
+        Ok(self
+            .reader
+            .read()
+            .map_err(|_| "GeoIP reader lock is poisoned")?
+            .clone())
+    }
+
+    pub fn get_country(&self, ip: IpAddr) -> Result<Option<String>, Box<dyn Error>> {
+        let reader = self.get_reader()?;
+        let result = reader.lookup(ip)?;
+        let country: Option<maxminddb::geoip2::Country> = result.decode()?;
+        Ok(country.and_then(|country| country.country.iso_code.map(|code| code.to_string())))
+    }
+
+    /// Refreshes the database if it is older than the configured maximum age. Fails if the
+    /// database is still stale afterwards and stale databases are refused.
+    pub async fn ensure_fresh(&self) -> Result<(), Box<dyn Error>> {
+        let Some(max_age) = self.max_age else {
+            return Ok(());
+        };
+        let age = get_database_age(&*self.get_reader()?);
+        if age <= max_age {
+            return Ok(());
+        }
+        warn!(
+            database = self.name,
+            age_days = age.as_secs() / 86400,
+            "GeoIP database is stale"
+        );
+
+        if let Some(updater) = &self.updater {
+            info!(
+                database = self.name,
+                url = updater.config.url,
+                "Refreshing GeoIP database"
+            );
+            match self.update_database(updater).await {
+                Ok(()) => info!(database = self.name, "Refreshed GeoIP database"),
+                Err(e) => {
+                    warn!(database = self.name, error = %e, "Failed to refresh GeoIP database")
+                }
+            }
+        }
+
+        if self.refuse_stale && get_database_age(&*self.get_reader()?) > max_age {
+            return Err(format!("GeoIP database {} is stale", self.name).into());
+        }
+        Ok(())
+    }
+
+    async fn update_database(&self, updater: &Updater) -> Result<(), Box<dyn Error>> {
+        let update = &updater.config;
+        let database = download(&updater.client, &update.url, update.max_size).await?;
+
+        if let Some(sha256_url) = &update.sha256_url {
+            let checksum_file = download(&updater.client, sha256_url, update.max_size).await?;
+            verify_checksum(&database, &String::from_utf8_lossy(&checksum_file))?;
+        }
+
+        tokio::fs::create_dir_all(&update.cache_dir).await?;
+        let cached_path = update.cache_dir.join(format!("{}.mmdb", self.name));
+        let temporary_path = cached_path.with_extension("tmp");
+        let current_epoch = self.get_reader()?.metadata().build_epoch;
+        let reader =
+            install_database(database, current_epoch, &temporary_path, &cached_path).await?;
+
+        *self
+            .reader
+            .write()
+            .map_err(|_| "GeoIP reader lock is poisoned")? = Arc::new(reader);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// SHA-256 of `hello`
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_age() {
+        let now = UNIX_EPOCH + Duration::from_secs(31 * 86400);
+        assert_eq!(get_age(86400, now), Duration::from_secs(30 * 86400));
+        // a build date in the future is not stale
+        assert_eq!(get_age(32 * 86400, now), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_database_is_not_kept() {
+        let cache_dir = std::env::temp_dir().join(format!("mmdb-test-{}", rand::random::<u32>()));
+        std::fs::create_dir_all(&cache_dir).expect("Cannot create cache directory");
+        let cached_path = cache_dir.join("test.mmdb");
+        let temporary_path = cached_path.with_extension("tmp");
+
+        let result = install_database(b"hello".to_vec(), 0, &temporary_path, &cached_path).await;
+        assert!(result.is_err());
+        assert!(!temporary_path.exists());
+        assert!(!cached_path.exists());
+        std::fs::remove_dir_all(&cache_dir).expect("Cannot remove cache directory");
+    }
+
+    #[tokio::test]
+    async fn test_download_size_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Cannot bind");
+        let address = listener.local_addr().expect("No local address");
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.expect("Cannot accept");
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                // no content length, so the limit is enforced while reading
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nhello")
+                    .await
+                    .expect("Cannot write");
+            }
+        });
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/", address);
+
+        assert_eq!(
+            download(&client, &url, 5).await.expect("Download failed"),
+            b"hello".to_vec()
+        );
+        assert!(download(&client, &url, 4).await.is_err());
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert!(verify_checksum(b"hello", HELLO_SHA256).is_ok());
+        assert!(verify_checksum(
+            b"hello",
+            &format!("{}  GeoLite2-Country.mmdb\n", HELLO_SHA256.to_uppercase())
+        )
+        .is_ok());
+        assert!(verify_checksum(b"hello!", HELLO_SHA256).is_err());
+        assert!(verify_checksum(b"hello", "").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Countries declared by server operators, as a JSON object mapping hosts to ISO country codes
+pub struct Overrides {
+    countries: HashMap<String, String>,
+}
+
+impl Overrides {
+    pub fn new(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+        let countries = serde_json::from_str::<HashMap<String, String>>(content)?
+            .into_iter()
+            .map(|(host, country)| (host.to_lowercase(), country.to_uppercase()))
+            .collect();
+        Ok(Self { countries })
+    }
+
+    pub fn get_country(&self, host: &str) -> Option<String> {
+        self.countries.get(&host.to_lowercase()).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_insensitive() {
+        let overrides =
+            Overrides::parse(r#"{"SMP.Example.com": "de", "1.2.3.4": "NL"}"#).expect("valid");
+        assert_eq!(
+            overrides.get_country("smp.example.COM"),
+            Some("DE".to_string())
+        );
+        assert_eq!(overrides.get_country("1.2.3.4"), Some("NL".to_string()));
+        assert_eq!(overrides.get_country("example.com"), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Overrides::parse(r#"["smp.example.com"]"#).is_err());
+        assert!(Overrides::parse(r#"{"smp.example.com": 49}"#).is_err());
+    }
+}
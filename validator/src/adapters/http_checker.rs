@@ -1,57 +1,588 @@
 use crate::{
-    adapters::domain_type::{parse_origin, Type},
-    validator::ports::HttpCheckerPort,
+    adapters::{
+        info_page::parse_info_page, resolver::PublicResolver, tls_certificate::parse_certificate,
+    },
+    validator::{
+        domain_type::{get_reserved_range, parse_origin, Host, Type},
+        ports::{
+            BodyOutcome, HttpCheckerPort, HttpStatus, InfoPageResponse, ResolverPort,
+            TlsCertificate,
+        },
+    },
 };
+use chrono::Utc;
 use reqwest;
+use std::error::Error;
+use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
+pub struct HttpCheckerConfiguration {
+    pub tor_socks5_proxy: String,
+    /// Gives every target host its own Tor circuits through distinct SOCKS credentials
+    pub isolate_tor_streams: bool,
+    pub i2p_proxy: Option<String>,
+    pub tls_expiry_warning: Duration,
+    /// Schemes tried in order for clearnet hosts; overlay networks are encrypted already and
+    /// are only probed over http
+    pub clearnet_schemes: Vec<String>,
+    pub max_redirects: usize,
+    pub timeout: Duration,
+    pub user_agent: String,
+    pub max_body_size: usize,
+    /// Media types whose bodies are read; responses without a content type are read too
+    pub allowed_content_types: Vec<String>,
+}
+
+/// Clients are built once so connection pools and TLS sessions are reused across the run
+struct Clients {
+    /// Keeps the TLS details of responses, so the certificate comes with the info page
+    clearnet: reqwest::Client,
+    tor: reqwest::Client,
+    yggdrasil: reqwest::Client,
+    i2p: Option<reqwest::Client>,
+    /// Fetch certificates of hosts whose info page wasn't served over HTTPS by the host itself;
+    /// redirects aren't followed so the certificate is the host's own
+    certificate: reqwest::Client,
+    unverified_certificate: reqwest::Client,
+}
+
+/// Info page and the DER of the certificate the host presented for it
+struct FetchedPage {
+    response: InfoPageResponse,
+    certificate: Option<Vec<u8>>,
+}
+
 pub struct HttpChecker {
-    tor_socks5_proxy: String,
+    config: HttpCheckerConfiguration,
+    clients: Clients,
+}
+
+fn get_url(scheme: &str, host_info: &Host) -> String {
+    match host_info.port {
+        Some(p) => format!("{scheme}://{}:{}", host_info.value, p),
+        None => format!("{scheme}://{}", host_info.value),
+    }
+}
+
+/// Tor builds separate circuits for streams with different SOCKS credentials, so the target
+/// host and a per-run nonce are sent as credentials
+fn isolated_tor_proxy(proxy: &str) -> Result<reqwest::Proxy, Box<dyn Error>> {
+    let proxy = reqwest::Url::parse(proxy)?;
+    let nonce = rand::random::<u32>().to_string();
+    Ok(reqwest::Proxy::custom(move |url| {
+        let mut proxy = proxy.clone();
+        proxy.set_username(url.host_str()?).ok()?;
+        proxy.set_password(Some(&nonce)).ok()?;
+        Some(proxy)
+    }))
+}
+
+fn client_builder<R: ResolverPort + Send + Sync + 'static>(
+    config: &HttpCheckerConfiguration,
+    resolver: &Arc<PublicResolver<R>>,
+) -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .dns_resolver(resolver.clone())
+        .timeout(config.timeout)
+        .user_agent(&config.user_agent)
+        .redirect(redirect_policy(config.max_redirects))
+}
+
+/// Whether a redirect may be followed. Names are vetted by `PublicResolver` when each hop is
+/// connected, but IP literals never reach a resolver and are checked here.
+fn is_allowed_redirect(url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) => get_reserved_range(&ip).is_none(),
+        // resolved locally by some stub resolvers without a DNS query
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            host != "localhost" && !host.ends_with(".localhost")
+        }
+    }
+}
+
+/// Follows up to `max_redirects` redirects; a redirect to a non-public address is not followed
+/// and its response is returned as is
+fn redirect_policy(max_redirects: usize) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > max_redirects {
+            attempt.error("Too many redirects")
+        } else if !is_allowed_redirect(attempt.url()) {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Whether the request failed because the server's certificate did not verify, as opposed to
+/// a timeout, a refused connection or an HTTP error
+fn is_certificate_error(error: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        // `io::Error::source` skips the error it wraps, so that one is checked explicitly
+        let inner = error
+            .downcast_ref::<std::io::Error>()
+            .and_then(|e| e.get_ref())
+            .map(|e| e as &(dyn Error + 'static));
+        for error in std::iter::once(error).chain(inner) {
+            if matches!(
+                error.downcast_ref::<rustls::Error>(),
+                Some(rustls::Error::InvalidCertificate(_))
+            ) {
+                return true;
+            }
+        }
+        source = error.source();
+    }
+    false
+}
+
+fn get_peer_certificate(response: &reqwest::Response) -> Option<Vec<u8>> {
+    Some(
+        response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()?
+            .peer_certificate()?
+            .to_vec(),
+    )
+}
+
+const DOCUMENT_END: &[u8] = b"</html>";
+
+fn contains_document_end(body: &[u8]) -> bool {
+    body.windows(DOCUMENT_END.len())
+        .any(|window| window.eq_ignore_ascii_case(DOCUMENT_END))
+}
+
+fn is_allowed_content_type(content_type: Option<&str>, allowed: &[String]) -> bool {
+    let Some(content_type) = content_type else {
+        return true;
+    };
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    allowed
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(media_type))
+}
+
+/// Streams the body until the end of the HTML document, giving up once it exceeds `max_size`
+/// bytes. Returns `None` for oversized bodies.
+async fn read_body(
+    response: &mut reqwest::Response,
+    max_size: usize,
+) -> Result<Option<String>, Box<dyn Error>> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_size as u64)
+    {
+        return Ok(None);
+    }
+    let mut body: Vec<u8> = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_size {
+            return Ok(None);
+        }
+        // the end tag may be split between chunks
+        let search_from = body.len().saturating_sub(DOCUMENT_END.len() - 1);
+        body.extend_from_slice(&chunk);
+        if contains_document_end(&body[search_from..]) {
+            break;
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
 }
 
 impl HttpChecker {
-    pub fn new(tor_socks5_proxy: String) -> Self {
-        Self { tor_socks5_proxy }
+    /// The clients resolve clearnet names through `resolver`
+    pub fn new<R: ResolverPort + Send + Sync + 'static>(
+        config: HttpCheckerConfiguration,
+        resolver: R,
+    ) -> Result<Self, Box<dyn Error>> {
+        let resolver = Arc::new(PublicResolver(Arc::new(resolver)));
+        let client_builder = |config| client_builder(config, &resolver);
+        let clients = Clients {
+            clearnet: client_builder(&config).tls_info(true).build()?,
+            tor: client_builder(&config)
+                .proxy(if config.isolate_tor_streams {
+                    isolated_tor_proxy(&config.tor_socks5_proxy)?
+                } else {
+                    reqwest::Proxy::all(&config.tor_socks5_proxy)?
+                })
+                .build()?,
+            yggdrasil: client_builder(&config).build()?,
+            i2p: match &config.i2p_proxy {
+                Some(proxy) => Some(
+                    client_builder(&config)
+                        .proxy(reqwest::Proxy::all(proxy)?)
+                        .build()?,
+                ),
+                None => None,
+            },
+            certificate: client_builder(&config)
+                .tls_info(true)
+                .redirect(reqwest::redirect::Policy::none())
+                .build()?,
+            unverified_certificate: client_builder(&config)
+                .tls_info(true)
+                .redirect(reqwest::redirect::Policy::none())
+                .danger_accept_invalid_certs(true)
+                .build()?,
+        };
+        Ok(Self { config, clients })
+    }
+
+    fn get_client(&self, domain_type: &Type) -> Result<&reqwest::Client, Box<dyn Error>> {
+        match domain_type {
+            Type::Clearnet => Ok(&self.clients.clearnet),
+            Type::Onion => Ok(&self.clients.tor),
+            Type::Yggdrasil => Ok(&self.clients.yggdrasil),
+            Type::I2p => self
+                .clients
+                .i2p
+                .as_ref()
+                .ok_or_else(|| "I2P proxy is not configured".into()),
+        }
     }
 
-    async fn _is_page_available(&self, host: &str) -> Result<bool, Box<dyn std::error::Error>> {
-        let host_info = parse_origin(host);
-        let (use_proxy, is_https) = match host_info.domain_type {
-            Type::Onion => (true, false),
-            Type::Clearnet => (false, true),
-            Type::Yggdrasil => (false, false),
+    async fn fetch_info_page(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        host_info: &Host,
+    ) -> Result<FetchedPage, Box<dyn Error>> {
+        let mut response = client.get(url).send().await?;
+        let final_url = response.url().clone();
+        let certificate = get_peer_certificate(&response);
+        let status_code = response.status().as_u16();
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
         };
+        let content_type = header(reqwest::header::CONTENT_TYPE);
+        // bodies are never decompressed, so an encoded one can't be parsed safely
+        let is_encoded = header(reqwest::header::CONTENT_ENCODING)
+            .is_some_and(|encoding| !encoding.eq_ignore_ascii_case("identity"));
 
-        let client = if use_proxy {
-            reqwest::Client::builder()
-                .proxy(reqwest::Proxy::all(&self.tor_socks5_proxy)?)
-                .build()?
+        let (body, page) = if is_encoded {
+            (BodyOutcome::UnsafeEncoding, None)
+        } else if !is_allowed_content_type(
+            content_type.as_deref(),
+            &self.config.allowed_content_types,
+        ) {
+            (BodyOutcome::UnsafeContentType, None)
         } else {
-            reqwest::Client::new()
+            match read_body(&mut response, self.config.max_body_size).await? {
+                Some(text) => (BodyOutcome::Read, parse_info_page(&text)),
+                None => (BodyOutcome::Oversized, None),
+            }
         };
+        let redirected = final_url.host_str().is_some_and(|final_host| {
+            let final_host = final_host.trim_start_matches('[').trim_end_matches(']');
+            !final_host.eq_ignore_ascii_case(&host_info.value)
+        });
+        Ok(FetchedPage {
+            // a certificate of the host a redirect led to says nothing about this one
+            certificate: certificate.filter(|_| final_url.scheme() == "https" && !redirected),
+            response: InfoPageResponse {
+                final_url: final_url.to_string(),
+                status_code,
+                content_type,
+                body,
+                redirected_away: redirected && page.is_none(),
+                page,
+            },
+        })
+    }
 
-        let scheme = if is_https { "https" } else { "http" };
-        let url = match host_info.port {
-            Some(p) => format!("{scheme}://{}:{}", host_info.value, p),
-            None => format!("{scheme}://{}", host_info.value),
+    /// DER of the leaf certificate, with or without verifying the chain and hostname
+    async fn fetch_certificate(&self, url: &str, verify: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+        let client = if verify {
+            &self.clients.certificate
+        } else {
+            &self.clients.unverified_certificate
         };
+        let response = client.get(url).send().await?;
+        Ok(get_peer_certificate(&response).ok_or("No peer certificate")?)
+    }
 
-        if let Ok(response) = client
-            .get(&url)
-            .timeout(Duration::from_secs(5))
-            .send()
-            .await
-        {
-            if let Ok(text) = response.text().await {
-                return Ok(text.to_lowercase().contains("simplex"));
+    /// Certificate of a clearnet host whose info page didn't provide a verified one. A
+    /// certificate that fails verification is still fetched to report what is wrong with it.
+    async fn get_certificate(
+        &self,
+        host_info: &Host,
+        failed_verification: bool,
+    ) -> Result<TlsCertificate, Box<dyn Error>> {
+        let url = get_url("https", host_info);
+        let (der, chain_valid) = if failed_verification {
+            (self.fetch_certificate(&url, false).await?, false)
+        } else {
+            match self.fetch_certificate(&url, true).await {
+                Ok(der) => (der, true),
+                Err(e) if is_certificate_error(e.as_ref()) => {
+                    (self.fetch_certificate(&url, false).await?, false)
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        parse_certificate(
+            &der,
+            &host_info.value,
+            chain_valid,
+            self.config.tls_expiry_warning,
+            Utc::now(),
+        )
+    }
+
+    async fn _check_http(&self, host: &str) -> Result<HttpStatus, Box<dyn Error>> {
+        let host_info = parse_origin(host)?;
+        let client = self.get_client(&host_info.domain_type)?;
+        let is_clearnet = host_info.domain_type == Type::Clearnet;
+        let schemes = if is_clearnet {
+            self.config.clearnet_schemes.clone()
+        } else {
+            vec!["http".to_string()]
+        };
+
+        // the first SimpleX page wins; otherwise the first response is reported
+        let mut first_response = None;
+        let mut certificate = None;
+        let mut failed_verification = false;
+        for scheme in schemes {
+            let url = get_url(&scheme, &host_info);
+            match self.fetch_info_page(client, &url, &host_info).await {
+                Ok(fetched) => {
+                    certificate = certificate.or(fetched.certificate);
+                    if fetched.response.page.is_some() {
+                        first_response = Some(fetched.response);
+                        break;
+                    }
+                    first_response.get_or_insert(fetched.response);
+                }
+                Err(e) => failed_verification |= is_certificate_error(e.as_ref()),
             }
         }
-        Ok(false)
+
+        let tls_certificate = match certificate {
+            Some(der) => Some(parse_certificate(
+                &der,
+                &host_info.value,
+                true,
+                self.config.tls_expiry_warning,
+                Utc::now(),
+            )?),
+            None if is_clearnet => self
+                .get_certificate(&host_info, failed_verification)
+                .await
+                .ok(),
+            None => None,
+        };
+        Ok(HttpStatus {
+            info_page: first_response,
+            tls_certificate,
+        })
     }
 }
 
 impl HttpCheckerPort for HttpChecker {
-    async fn is_page_available(&self, host: &str) -> bool {
-        self._is_page_available(host).await.unwrap_or(false)
+    async fn check_http(&self, host: &str) -> HttpStatus {
+        self._check_http(host).await.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator::ports::{AddressFamily, DnsLookup};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const MAX_BODY_SIZE: usize = 64;
+
+    /// Resolves every name to `addresses`; `None` is a failed lookup
+    struct FakeResolver {
+        addresses: Option<Vec<IpAddr>>,
+    }
+
+    impl ResolverPort for FakeResolver {
+        async fn resolve(&self, _host: &str) -> Option<Vec<IpAddr>> {
+            self.addresses.clone()
+        }
+
+        async fn lookup(&self, _host: &str, _family: AddressFamily) -> Option<DnsLookup> {
+            None
+        }
+    }
+
+    fn get_checker() -> HttpChecker {
+        HttpChecker::new(
+            HttpCheckerConfiguration {
+                tor_socks5_proxy: "socks5h://127.0.0.1:9050".to_string(),
+                isolate_tor_streams: false,
+                i2p_proxy: None,
+                tls_expiry_warning: Duration::from_secs(0),
+                clearnet_schemes: vec!["http".to_string()],
+                max_redirects: 0,
+                timeout: Duration::from_secs(2),
+                user_agent: "test".to_string(),
+                max_body_size: MAX_BODY_SIZE,
+                allowed_content_types: vec!["text/html".to_string()],
+            },
+            FakeResolver { addresses: None },
+        )
+        .expect("Cannot create HTTP checker")
+    }
+
+    fn chunk(data: &str) -> String {
+        format!("{:x}\r\n{}\r\n", data.len(), data)
+    }
+
+    /// Serves one connection, writing `parts` separately and keeping the connection open
+    /// afterwards, so a read that doesn't stop at the document end runs into the timeout
+    async fn serve(parts: Vec<String>) -> Result<FetchedPage, Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("Cannot accept");
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            for part in parts {
+                stream
+                    .write_all(part.as_bytes())
+                    .await
+                    .expect("Cannot write");
+                stream.flush().await.expect("Cannot flush");
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+        let checker = get_checker();
+        let host_info = parse_origin(&address.to_string())?;
+        checker
+            .fetch_info_page(
+                &checker.clients.clearnet,
+                &format!("http://{}/", address),
+                &host_info,
+            )
+            .await
+    }
+
+    fn chunked_headers(extra: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n{}Transfer-Encoding: chunked\r\n\r\n",
+            extra
+        )
+    }
+
+    #[tokio::test]
+    async fn test_split_document_end() {
+        let fetched = serve(vec![
+            chunked_headers(""),
+            chunk("<html><body>hi</body></ht"),
+            chunk("ml>"),
+        ])
+        .await
+        .expect("Reading stopped at the document end");
+        assert_eq!(fetched.response.body, BodyOutcome::Read);
+        assert_eq!(fetched.response.status_code, 200);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body() {
+        let declared = serve(vec![format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        )])
+        .await
+        .expect("Declared length is checked before reading");
+        assert_eq!(declared.response.body, BodyOutcome::Oversized);
+
+        let streamed = serve(vec![
+            chunked_headers(""),
+            chunk(&"a".repeat(MAX_BODY_SIZE / 2)),
+            chunk(&"a".repeat(MAX_BODY_SIZE / 2 + 1)),
+        ])
+        .await
+        .expect("Reading stopped at the limit");
+        assert_eq!(streamed.response.body, BodyOutcome::Oversized);
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_content_type() {
+        let fetched = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nTransfer-Encoding: chunked\r\n\r\n".to_string(),
+        ])
+        .await
+        .expect("Body is not read");
+        assert_eq!(fetched.response.body, BodyOutcome::UnsafeContentType);
+        assert!(fetched.response.page.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_content_encoding() {
+        let fetched = serve(vec![chunked_headers("Content-Encoding: gzip\r\n")])
+            .await
+            .expect("Body is not read");
+        assert_eq!(fetched.response.body, BodyOutcome::UnsafeEncoding);
+
+        let identity = serve(vec![
+            chunked_headers("Content-Encoding: identity\r\n"),
+            chunk("<html></html>"),
+        ])
+        .await
+        .expect("Identity encoding is read");
+        assert_eq!(identity.response.body, BodyOutcome::Read);
+    }
+
+    #[test]
+    fn test_allowed_content_type() {
+        let allowed = vec!["text/html".to_string()];
+        assert!(is_allowed_content_type(None, &allowed));
+        assert!(is_allowed_content_type(
+            Some("Text/HTML; charset=utf-8"),
+            &allowed
+        ));
+        assert!(!is_allowed_content_type(Some("image/svg+xml"), &allowed));
+    }
+
+    #[test]
+    fn test_allowed_redirect() {
+        let is_allowed = |url: &str| is_allowed_redirect(&url.parse().expect("Invalid URL"));
+        assert!(is_allowed("https://example.com/info"));
+        assert!(is_allowed("http://93.184.215.14/"));
+        assert!(is_allowed("http://[2606:4700::1]:8080/"));
+        assert!(!is_allowed("http://127.0.0.1/"));
+        assert!(!is_allowed("http://169.254.169.254/latest/meta-data"));
+        assert!(!is_allowed("http://10.1.2.3:8080/"));
+        assert!(!is_allowed("http://[::1]/"));
+        assert!(!is_allowed("http://[fd00::1]/"));
+        assert!(!is_allowed("http://[::ffff:192.168.0.1]/"));
+        assert!(!is_allowed("http://localhost:8080/"));
+        assert!(!is_allowed("http://admin.LOCALHOST./"));
+    }
+
+    #[test]
+    fn test_certificate_error() {
+        let expired = std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(rustls::CertificateError::Expired),
+        );
+        assert!(is_certificate_error(&expired));
+
+        let timeout = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+        assert!(!is_certificate_error(&timeout));
+
+        let handshake = std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::HandshakeNotComplete,
+        );
+        assert!(!is_certificate_error(&handshake));
     }
 }
@@ -0,0 +1,232 @@
+use crate::validator::ports::InfoPage;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static SCRIPT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<script\b.*?</script>").expect("valid regex"));
+static STYLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<style\b.*?</style>").expect("valid regex"));
+static LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<a\s[^>]*?href\s*=\s*["']([^"']+)["'][^>]*>(.*?)</a>"#).expect("valid regex")
+});
+static BLOCK_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)</?(br|p|div|tr|th|td|li|ul|ol|dt|dd|dl|h[1-6]|section|table|header|footer|title)\b[^>]*>")
+        .expect("valid regex")
+});
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").expect("valid regex"));
+static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").expect("valid regex"));
+static VERSION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bv?(\d+(?:\.\d+){1,3})\b").expect("valid regex"));
+
+struct Link {
+    href: String,
+    text: String,
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn to_text(html: &str) -> String {
+    let text = TAG.replace_all(html, "");
+    WHITESPACE
+        .replace_all(&decode_entities(&text), " ")
+        .trim()
+        .to_string()
+}
+
+/// Visible text of the page, one line per block element
+fn to_lines(html: &str) -> Vec<String> {
+    let html = BLOCK_TAG.replace_all(html, "\n");
+    html.lines()
+        .map(to_text)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Value of the first `Label: value` line, or of the line following a lone `Label:` line
+fn find_value(lines: &[String], labels: &[&str]) -> Option<String> {
+    for (index, line) in lines.iter().enumerate() {
+        for label in labels {
+            let Some(rest) = line
+                .get(..label.len())
+                .filter(|prefix| prefix.eq_ignore_ascii_case(label))
+                .and_then(|_| line.get(label.len()..))
+            else {
+                continue;
+            };
+            if !rest.is_empty() && !rest.starts_with(':') {
+                continue;
+            }
+            let value = rest.trim_start_matches(':').trim();
+            if !value.is_empty() {
+                return Some(value.to_string());
+            }
+            return lines
+                .get(index + 1)
+                .filter(|next| !next.ends_with(':'))
+                .cloned();
+        }
+    }
+    None
+}
+
+fn is_contact_link(href: &str) -> bool {
+    href.starts_with("mailto:")
+        || href.starts_with("simplex:/contact")
+        || href.contains("simplex.chat/contact#")
+        || href.contains("/a#")
+}
+
+/// Extracts what the operator filled into the info page generated by the SMP/XFTP server.
+/// Returns `None` if the page isn't a SimpleX server page at all.
+pub fn parse_info_page(html: &str) -> Option<InfoPage> {
+    if !html.to_lowercase().contains("simplex") {
+        return None;
+    }
+
+    let html = STYLE
+        .replace_all(&SCRIPT.replace_all(html, ""), "")
+        .to_string();
+    let links: Vec<Link> = LINK
+        .captures_iter(&html)
+        .map(|captures| Link {
+            href: decode_entities(captures[1].trim()),
+            text: to_text(&captures[2]),
+        })
+        .collect();
+    let lines = to_lines(&html);
+
+    let mut contacts: Vec<String> = vec![];
+    for link in links.iter().filter(|link| is_contact_link(&link.href)) {
+        let contact = link.href.trim_start_matches("mailto:").to_string();
+        if !contacts.contains(&contact) {
+            contacts.push(contact);
+        }
+    }
+
+    let server_name = find_value(&lines, &["server name", "name"]);
+    let operator = find_value(&lines, &["operator", "server operator", "operator entity"]);
+    let source_code = links
+        .iter()
+        .find(|link| link.text.to_lowercase().contains("source code"))
+        .map(|link| link.href.clone())
+        .or_else(|| find_value(&lines, &["source code"]).filter(|value| value.starts_with("http")))
+        .or_else(|| {
+            links
+                .iter()
+                .find(|link| link.href.contains("simplexmq"))
+                .map(|link| link.href.clone())
+        });
+    let server_version = find_value(&lines, &["server version", "version"])
+        .and_then(|value| VERSION.captures(&value).map(|c| c[1].to_string()));
+    let hosting_country = find_value(&lines, &["hosting country", "server country", "country"]);
+    let usage_conditions = links
+        .iter()
+        .find(|link| link.text.to_lowercase().contains("conditions"))
+        .map(|link| link.href.clone())
+        .or_else(|| find_value(&lines, &["usage conditions", "conditions"]));
+
+    let is_stock_template = server_name.is_none()
+        && operator.is_none()
+        && contacts.is_empty()
+        && hosting_country.is_none()
+        && usage_conditions.is_none();
+
+    Some(InfoPage {
+        server_name,
+        operator,
+        contacts,
+        source_code,
+        server_version,
+        hosting_country,
+        usage_conditions,
+        is_stock_template,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIGURED: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>SimpleX SMP server</title><style>td { color: red; }</style></head>
+<body>
+<h1>SimpleX Chat &amp; SMP server</h1>
+<section>
+  <h2>Server information</h2>
+  <table>
+    <tr><th>Server name:</th><td>Example relay</td></tr>
+    <tr><th>Server version:</th><td>v6.3.4.0 (<a href="https://github.com/simplex-chat/simplexmq/commit/abc">abc</a>)</td></tr>
+    <tr><th>Operator:</th><td>Example Ltd</td></tr>
+    <tr><th>Hosting country:</th><td>DE</td></tr>
+  </table>
+  <p>Source code: <a href="https://github.com/example/simplexmq">https://github.com/example/simplexmq</a></p>
+  <p>Contact: <a href="mailto:admin@example.com">admin@example.com</a>,
+     <a href="https://simplex.chat/contact#/?v=2&amp;smp=abc">SimpleX address</a></p>
+  <p><a href="https://example.com/conditions">Usage conditions</a></p>
+</section>
+</body>
+</html>"#;
+
+    const STOCK: &str = r#"<html><head><title>SimpleX SMP server</title></head>
+<body><h1>SimpleX Chat</h1><p>Server version: 6.3.4</p>
+<p><a href="https://github.com/simplex-chat/simplexmq">Source code</a></p></body></html>"#;
+
+    #[test]
+    fn test_configured_page() {
+        let page = parse_info_page(CONFIGURED).expect("SimpleX page");
+        assert_eq!(page.server_name.as_deref(), Some("Example relay"));
+        assert_eq!(page.server_version.as_deref(), Some("6.3.4.0"));
+        assert_eq!(page.operator.as_deref(), Some("Example Ltd"));
+        assert_eq!(page.hosting_country.as_deref(), Some("DE"));
+        assert_eq!(
+            page.source_code.as_deref(),
+            Some("https://github.com/example/simplexmq")
+        );
+        assert_eq!(
+            page.contacts,
+            vec![
+                "admin@example.com",
+                "https://simplex.chat/contact#/?v=2&smp=abc"
+            ]
+        );
+        assert_eq!(
+            page.usage_conditions.as_deref(),
+            Some("https://example.com/conditions")
+        );
+        assert!(!page.is_stock_template);
+    }
+
+    #[test]
+    fn test_stock_page() {
+        let page = parse_info_page(STOCK).expect("SimpleX page");
+        assert_eq!(page.server_version.as_deref(), Some("6.3.4"));
+        assert_eq!(
+            page.source_code.as_deref(),
+            Some("https://github.com/simplex-chat/simplexmq")
+        );
+        assert!(page.is_stock_template);
+    }
+
+    #[test]
+    fn test_label_without_value() {
+        let lines = to_lines("<p>Operator:</p><p>Hosting country:</p><p>DE</p>");
+        assert_eq!(find_value(&lines, &["operator"]), None);
+        assert_eq!(
+            find_value(&lines, &["hosting country"]),
+            Some("DE".to_string())
+        );
+    }
+
+    #[test]
+    fn test_not_simplex_page() {
+        assert_eq!(parse_info_page("<html><body>It works!</body></html>"), None);
+    }
+}
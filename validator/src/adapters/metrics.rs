@@ -0,0 +1,303 @@
+use crate::validator::ports::{CheckOutcome, MetricsPort, NetworkType};
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info};
+
+/// A client that doesn't send its request in time is dropped, so idle connections can't pile up
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bounds in seconds; checks through Tor with retries can take minutes
+const CHECK_DURATION_BUCKETS: [f64; 9] = [0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+#[derive(Default)]
+struct State {
+    /// Keyed by network type and outcome
+    checks: BTreeMap<(&'static str, &'static str), u64>,
+    check_duration_buckets: [u64; CHECK_DURATION_BUCKETS.len()],
+    check_duration_sum: f64,
+    check_duration_count: u64,
+    retries: u64,
+    repository_write_failures: u64,
+    geoip_misses: u64,
+    run_duration: Option<f64>,
+    last_successful_run: Option<i64>,
+}
+
+/// Counters kept in memory for the lifetime of the process and exposed in the Prometheus text
+/// format
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+fn write_header(output: &mut String, name: &str, type_: &str, help: &str) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, type_);
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        f(&mut self.state.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut output = String::new();
+
+        write_header(
+            &mut output,
+            "validator_checks_total",
+            "counter",
+            "Host checks by network type and outcome",
+        );
+        for ((network, outcome), count) in &state.checks {
+            let _ = writeln!(
+                output,
+                "validator_checks_total{{network=\"{}\",outcome=\"{}\"}} {}",
+                network, outcome, count
+            );
+        }
+
+        write_header(
+            &mut output,
+            "validator_check_duration_seconds",
+            "histogram",
+            "Time to check a server, including retries and its hosts",
+        );
+        for (bound, count) in CHECK_DURATION_BUCKETS
+            .iter()
+            .zip(state.check_duration_buckets)
+        {
+            let _ = writeln!(
+                output,
+                "validator_check_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            );
+        }
+        let _ = writeln!(
+            output,
+            "validator_check_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            state.check_duration_count
+        );
+        let _ = writeln!(
+            output,
+            "validator_check_duration_seconds_sum {}",
+            state.check_duration_sum
+        );
+        let _ = writeln!(
+            output,
+            "validator_check_duration_seconds_count {}",
+            state.check_duration_count
+        );
+
+        for (name, help, value) in [
+            (
+                "validator_check_retries_total",
+                "Server tests repeated after a failure",
+                state.retries,
+            ),
+            (
+                "validator_repository_write_failures_total",
+                "Check results that could not be stored",
+                state.repository_write_failures,
+            ),
+            (
+                "validator_geoip_misses_total",
+                "Clearnet hosts without a known country",
+                state.geoip_misses,
+            ),
+        ] {
+            write_header(&mut output, name, "counter", help);
+            let _ = writeln!(output, "{} {}", name, value);
+        }
+
+        if let Some(duration) = state.run_duration {
+            write_header(
+                &mut output,
+                "validator_run_duration_seconds",
+                "gauge",
+                "Duration of the last run",
+            );
+            let _ = writeln!(output, "validator_run_duration_seconds {}", duration);
+        }
+        if let Some(timestamp) = state.last_successful_run {
+            write_header(
+                &mut output,
+                "validator_last_successful_run_timestamp_seconds",
+                "gauge",
+                "Unix time the last run that retrieved the servers finished",
+            );
+            let _ = writeln!(
+                output,
+                "validator_last_successful_run_timestamp_seconds {}",
+                timestamp
+            );
+        }
+        output
+    }
+}
+
+impl MetricsPort for Metrics {
+    fn record_check(&self, network: NetworkType, outcome: CheckOutcome) {
+        self.update(|state| {
+            *state
+                .checks
+                .entry((network.as_str(), outcome.as_str()))
+                .or_default() += 1;
+        });
+    }
+
+    fn record_check_duration(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        self.update(|state| {
+            for (bound, count) in CHECK_DURATION_BUCKETS
+                .iter()
+                .zip(state.check_duration_buckets.iter_mut())
+            {
+                if seconds <= *bound {
+                    *count += 1;
+                }
+            }
+            state.check_duration_sum += seconds;
+            state.check_duration_count += 1;
+        });
+    }
+
+    fn record_retry(&self) {
+        self.update(|state| state.retries += 1);
+    }
+
+    fn record_repository_write_failure(&self) {
+        self.update(|state| state.repository_write_failures += 1);
+    }
+
+    fn record_geoip_miss(&self) {
+        self.update(|state| state.geoip_misses += 1);
+    }
+
+    fn record_run(&self, duration: Duration, success: bool) {
+        self.update(|state| {
+            state.run_duration = Some(duration.as_secs_f64());
+            if success {
+                state.last_successful_run = Some(Utc::now().timestamp());
+            }
+        });
+    }
+}
+
+/// Answers a single request; anything but `GET /metrics` gets a 404
+async fn handle_connection(
+    metrics: &Metrics,
+    mut stream: TcpStream,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    let mut request = [0u8; 1024];
+    let length = tokio::time::timeout(timeout, stream.read(&mut request))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "No request received"))??;
+    let request_line = String::from_utf8_lossy(&request[..length]);
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = metrics.render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serves `/metrics` until the process exits
+pub async fn serve(metrics: Arc<Metrics>, listener: TcpListener) -> std::io::Result<()> {
+    info!(address = %listener.local_addr()?, "Serving metrics on /metrics");
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(&metrics, stream, REQUEST_TIMEOUT).await {
+                error!(error = %e, "Error serving metrics");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_checks() {
+        let metrics = Metrics::new();
+        metrics.record_check(NetworkType::Tor, CheckOutcome::Up);
+        metrics.record_check(NetworkType::Tor, CheckOutcome::Up);
+        metrics.record_check(NetworkType::Clearnet, CheckOutcome::Down);
+        let output = metrics.render();
+        assert!(output.contains("validator_checks_total{network=\"tor\",outcome=\"up\"} 2\n"));
+        assert!(
+            output.contains("validator_checks_total{network=\"clearnet\",outcome=\"down\"} 1\n")
+        );
+        assert!(!output.contains("validator_last_successful_run_timestamp_seconds"));
+    }
+
+    #[test]
+    fn test_render_histogram() {
+        let metrics = Metrics::new();
+        metrics.record_check_duration(Duration::from_secs(3));
+        metrics.record_check_duration(Duration::from_secs(400));
+        let output = metrics.render();
+        assert!(output.contains("validator_check_duration_seconds_bucket{le=\"2.5\"} 0\n"));
+        assert!(output.contains("validator_check_duration_seconds_bucket{le=\"5\"} 1\n"));
+        assert!(output.contains("validator_check_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(output.contains("validator_check_duration_seconds_sum 403\n"));
+    }
+
+    #[tokio::test]
+    async fn test_drops_silent_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Cannot bind");
+        let client = TcpStream::connect(listener.local_addr().expect("Bound address"))
+            .await
+            .expect("Cannot connect");
+        let (stream, _) = listener.accept().await.expect("Cannot accept");
+        let error = handle_connection(&Metrics::new(), stream, Duration::from_millis(50))
+            .await
+            .expect_err("Client never sent a request");
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        drop(client);
+    }
+
+    #[tokio::test]
+    async fn test_serves_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Cannot bind");
+        let mut client = TcpStream::connect(listener.local_addr().expect("Bound address"))
+            .await
+            .expect("Cannot connect");
+        let (stream, _) = listener.accept().await.expect("Cannot accept");
+        client
+            .write_all(b"GET /metrics HTTP/1.1\r\n\r\n")
+            .await
+            .expect("Cannot write");
+        handle_connection(&Metrics::new(), stream, Duration::from_secs(1))
+            .await
+            .expect("Request answered");
+        let mut response = String::new();
+        client
+            .read_to_string(&mut response)
+            .await
+            .expect("Cannot read");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+}
@@ -0,0 +1,520 @@
+use crate::validator::domain_type::get_reserved_range;
+use crate::validator::ports::{AddressFamily, DnsLookup, ResolverPort};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig};
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::proto::rr::{RData, RecordType};
+use hickory_resolver::TokioResolver;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DNS_PORT: u16 = 53;
+
+pub enum Upstream {
+    /// Name servers from the system configuration (`/etc/resolv.conf`)
+    System,
+    /// Plain DNS (UDP with TCP fallback) to the given name servers
+    NameServers(Vec<SocketAddr>),
+    /// DNS-over-HTTPS JSON API, optionally through a proxy (e.g. Tor)
+    DnsOverHttps { url: String, proxy: Option<String> },
+}
+
+pub struct ResolverConfiguration {
+    pub upstream: Upstream,
+    pub timeout: Duration,
+    pub cache_ttl: Duration,
+}
+
+enum Backend {
+    Dns(Box<TokioResolver>),
+    DnsOverHttps {
+        client: reqwest::Client,
+        url: String,
+    },
+}
+
+struct CacheEntry {
+    expires_at: Instant,
+    lookup: DnsLookup,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DohResponse {
+    status: u32,
+    #[serde(default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    type_: u16,
+    #[serde(rename = "TTL")]
+    ttl: u32,
+    data: String,
+}
+
+pub fn parse_name_server(value: &str) -> Result<SocketAddr, Box<dyn Error>> {
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let ip = value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .map_err(|_| format!("Invalid name server address: {}", value))?;
+    Ok(SocketAddr::new(ip, DNS_PORT))
+}
+
+/// Records of `record_type` and the CNAME chain of a DNS-over-HTTPS JSON answer
+fn parse_doh_response(body: &str, record_type: RecordType) -> Result<DnsLookup, Box<dyn Error>> {
+    let response = serde_json::from_str::<DohResponse>(body)?;
+
+    // 0 is NOERROR, 3 is NXDOMAIN; anything else is a resolution failure
+    if response.status != 0 && response.status != 3 {
+        return Err(format!("DNS-over-HTTPS status {}", response.status).into());
+    }
+
+    let expected = u16::from(record_type);
+    let cname = u16::from(RecordType::CNAME);
+    Ok(DnsLookup {
+        addresses: response
+            .answer
+            .iter()
+            .filter(|answer| answer.type_ == expected)
+            .filter_map(|answer| answer.data.parse::<IpAddr>().ok())
+            .collect(),
+        cname_chain: response
+            .answer
+            .iter()
+            .filter(|answer| answer.type_ == cname)
+            .map(|answer| answer.data.clone())
+            .collect(),
+        ttl: response.answer.iter().map(|answer| answer.ttl).min(),
+    })
+}
+
+pub struct Resolver {
+    backend: Backend,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<(String, RecordType), CacheEntry>>,
+}
+
+fn record_type(family: AddressFamily) -> RecordType {
+    match family {
+        AddressFamily::V4 => RecordType::A,
+        AddressFamily::V6 => RecordType::AAAA,
+    }
+}
+
+impl Resolver {
+    pub fn new(config: ResolverConfiguration) -> Result<Self, Box<dyn Error>> {
+        let backend = match config.upstream {
+            Upstream::System => {
+                let mut builder = TokioResolver::builder_tokio()?;
+                builder.options_mut().timeout = config.timeout;
+                Backend::Dns(Box::new(builder.build()))
+            }
+            Upstream::NameServers(servers) => {
+                let mut group = NameServerConfigGroup::new();
+                for server in servers {
+                    group.merge(NameServerConfigGroup::from_ips_clear(
+                        &[server.ip()],
+                        server.port(),
+                        true,
+                    ));
+                }
+                let mut builder = TokioResolver::builder_with_config(
+                    ResolverConfig::from_parts(None, vec![], group),
+                    TokioConnectionProvider::default(),
+                );
+                builder.options_mut().timeout = config.timeout;
+                Backend::Dns(Box::new(builder.build()))
+            }
+            Upstream::DnsOverHttps { url, proxy } => {
+                let mut builder = reqwest::Client::builder().timeout(config.timeout);
+                if let Some(proxy) = proxy {
+                    builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+                }
+                Backend::DnsOverHttps {
+                    client: builder.build()?,
+                    url,
+                }
+            }
+        };
+
+        Ok(Self {
+            backend,
+            timeout: config.timeout,
+            cache_ttl: config.cache_ttl,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn query(
+        &self,
+        host: &str,
+        record_type: RecordType,
+    ) -> Result<DnsLookup, Box<dyn Error>> {
+        match &self.backend {
+            Backend::Dns(resolver) => match resolver.lookup(host, record_type).await {
+                Ok(lookup) => Ok(DnsLookup {
+                    addresses: lookup.iter().filter_map(|data| data.ip_addr()).collect(),
+                    cname_chain: lookup
+                        .iter()
+                        .filter_map(|data| match data {
+                            RData::CNAME(cname) => Some(cname.to_string()),
+                            _ => None,
+                        })
+                        .collect(),
+                    ttl: lookup.record_iter().map(|record| record.ttl()).min(),
+                }),
+                Err(e) if e.is_no_records_found() => Ok(DnsLookup::default()),
+                Err(e) => Err(e.into()),
+            },
+            Backend::DnsOverHttps { client, url } => {
+                let body = client
+                    .get(url)
+                    .query(&[("name", host), ("type", &record_type.to_string())])
+                    .header("accept", "application/dns-json")
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                parse_doh_response(&body, record_type)
+            }
+        }
+    }
+
+    fn get_cached(&self, key: &(String, RecordType)) -> Option<DnsLookup> {
+        let cache = self.cache.lock().ok()?;
+        let entry = cache.get(key)?;
+        if entry.expires_at > Instant::now() {
+            Some(entry.lookup.clone())
+        } else {
+            None
+        }
+    }
+
+    fn put_cached(&self, key: (String, RecordType), lookup: &DnsLookup) {
+        let ttl = lookup
+            .ttl
+            .map(|ttl| Duration::from_secs(ttl.into()).min(self.cache_ttl))
+            .unwrap_or(self.cache_ttl);
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(
+                key,
+                CacheEntry {
+                    expires_at: Instant::now() + ttl,
+                    lookup: lookup.clone(),
+                },
+            );
+        }
+    }
+
+    async fn _lookup(
+        &self,
+        host: &str,
+        family: AddressFamily,
+    ) -> Result<DnsLookup, Box<dyn Error>> {
+        let key = (host.to_string(), record_type(family));
+        if let Some(lookup) = self.get_cached(&key) {
+            return Ok(lookup);
+        }
+
+        let lookup = tokio::time::timeout(self.timeout, self.query(host, key.1)).await??;
+        self.put_cached(key, &lookup);
+        Ok(lookup)
+    }
+
+    async fn _resolve(&self, host: &str) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        // errors are dropped before joining so the future stays `Send` for the HTTP clients
+        let (v4, v6) = tokio::join!(
+            self.lookup(host, AddressFamily::V4),
+            self.lookup(host, AddressFamily::V6)
+        );
+
+        let addresses: Vec<IpAddr> = match (v4, v6) {
+            (None, None) => return Err(format!("Cannot resolve {}", host).into()),
+            (v4, v6) => v4
+                .into_iter()
+                .chain(v6)
+                .flat_map(|lookup| lookup.addresses)
+                .collect(),
+        };
+        if addresses.is_empty() {
+            return Err(format!("No addresses found for {}", host).into());
+        }
+        Ok(addresses)
+    }
+}
+
+impl ResolverPort for Resolver {
+    async fn resolve(&self, host: &str) -> Option<Vec<IpAddr>> {
+        self._resolve(host).await.ok()
+    }
+
+    async fn lookup(&self, host: &str, family: AddressFamily) -> Option<DnsLookup> {
+        self._lookup(host, family).await.ok()
+    }
+}
+
+/// Resolves names for reqwest through the validator's resolver and drops non-public addresses,
+/// so a host that resolves differently than when it was checked, or a redirect, can't reach
+/// internal services
+pub struct PublicResolver<R: ResolverPort>(pub Arc<R>);
+
+impl<R: ResolverPort + Send + Sync + 'static> Resolve for PublicResolver<R> {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.0.clone();
+        Box::pin(async move {
+            let addresses = resolver
+                .resolve(name.as_str())
+                .await
+                .ok_or_else(|| format!("Cannot resolve {}", name.as_str()))?;
+            let public: Vec<SocketAddr> = addresses
+                .into_iter()
+                .filter(|ip| get_reserved_range(ip).is_none())
+                .map(|ip| SocketAddr::new(ip, 0))
+                .collect();
+            if public.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(public.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Resolves every name to `addresses`; `None` is a failed lookup
+    struct FakeResolver {
+        addresses: Option<Vec<IpAddr>>,
+    }
+
+    impl ResolverPort for FakeResolver {
+        async fn resolve(&self, _host: &str) -> Option<Vec<IpAddr>> {
+            self.addresses.clone()
+        }
+
+        async fn lookup(&self, _host: &str, _family: AddressFamily) -> Option<DnsLookup> {
+            None
+        }
+    }
+
+    fn parse_ip(ip: &str) -> IpAddr {
+        ip.parse().expect("Invalid address")
+    }
+
+    async fn resolve_public(addresses: Option<Vec<IpAddr>>) -> Result<Vec<SocketAddr>, String> {
+        let resolver = PublicResolver(Arc::new(FakeResolver { addresses }));
+        let name = "smp.example.com".parse::<Name>().expect("Valid name");
+        resolver
+            .resolve(name)
+            .await
+            .map(|addresses| addresses.collect())
+            .map_err(|e| e.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_public_resolver() {
+        let public = parse_ip("93.184.215.14");
+        assert_eq!(
+            resolve_public(Some(vec![parse_ip("10.0.0.1"), public])).await,
+            Ok(vec![SocketAddr::new(public, 0)])
+        );
+        assert!(resolve_public(Some(vec![parse_ip("127.0.0.1")]))
+            .await
+            .is_err());
+        assert!(resolve_public(None).await.is_err());
+    }
+
+    #[test]
+    fn test_name_server_ipv4() {
+        let addr = parse_name_server("1.1.1.1").expect("valid address");
+        assert_eq!(addr, "1.1.1.1:53".parse().expect("valid address"));
+    }
+
+    #[test]
+    fn test_name_server_ipv4_with_port() {
+        let addr = parse_name_server("1.1.1.1:5353").expect("valid address");
+        assert_eq!(addr, "1.1.1.1:5353".parse().expect("valid address"));
+    }
+
+    #[test]
+    fn test_name_server_ipv6() {
+        let addr = parse_name_server("2606:4700:4700::1111").expect("valid address");
+        assert_eq!(
+            addr,
+            "[2606:4700:4700::1111]:53".parse().expect("valid address")
+        );
+    }
+
+    #[test]
+    fn test_name_server_ipv6_bracketed_with_port() {
+        let addr = parse_name_server("[2606:4700:4700::1111]:5353").expect("valid address");
+        assert_eq!(
+            addr,
+            "[2606:4700:4700::1111]:5353"
+                .parse()
+                .expect("valid address")
+        );
+    }
+
+    #[test]
+    fn test_name_server_invalid() {
+        assert!(parse_name_server("dns.example.com").is_err());
+    }
+
+    const CNAME_ANSWER: &str = r#"{"Status":0,"Answer":[
+        {"name":"smp.example.com.","type":5,"TTL":300,"data":"edge.example.net."},
+        {"name":"edge.example.net.","type":1,"TTL":60,"data":"93.184.215.14"},
+        {"name":"edge.example.net.","type":28,"TTL":120,"data":"2606:2800:21f:cb07:6820:80da:af6b:8b2c"}
+    ]}"#;
+
+    #[test]
+    fn test_doh_answer() {
+        let v4 = parse_doh_response(CNAME_ANSWER, RecordType::A).expect("Valid answer");
+        assert_eq!(v4.addresses, vec![parse_ip("93.184.215.14")]);
+        assert_eq!(v4.cname_chain, vec!["edge.example.net.".to_string()]);
+        assert_eq!(v4.ttl, Some(60));
+
+        let v6 = parse_doh_response(CNAME_ANSWER, RecordType::AAAA).expect("Valid answer");
+        assert_eq!(
+            v6.addresses,
+            vec![parse_ip("2606:2800:21f:cb07:6820:80da:af6b:8b2c")]
+        );
+        assert_eq!(v6.cname_chain, vec!["edge.example.net.".to_string()]);
+    }
+
+    #[test]
+    fn test_doh_status() {
+        // NXDOMAIN is an answer without records, other errors are resolution failures
+        let nxdomain = parse_doh_response(r#"{"Status":3}"#, RecordType::A).expect("Valid answer");
+        assert!(nxdomain.addresses.is_empty());
+        assert_eq!(nxdomain.ttl, None);
+        assert!(parse_doh_response(r#"{"Status":2}"#, RecordType::A).is_err());
+        assert!(parse_doh_response("not json", RecordType::A).is_err());
+    }
+
+    /// Answers every request with `body` after `delay`; returns the endpoint URL and the number
+    /// of requests served
+    async fn serve_doh(body: &'static str, delay: Duration) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Cannot bind");
+        let url = format!(
+            "http://{}/dns-query",
+            listener.local_addr().expect("No local address")
+        );
+        let requests = Arc::new(AtomicUsize::new(0));
+        let served = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.expect("Cannot accept");
+                served.fetch_add(1, Ordering::SeqCst);
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                tokio::time::sleep(delay).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, requests)
+    }
+
+    fn get_resolver(url: String, timeout: Duration, cache_ttl: Duration) -> Resolver {
+        Resolver::new(ResolverConfiguration {
+            upstream: Upstream::DnsOverHttps { url, proxy: None },
+            timeout,
+            cache_ttl,
+        })
+        .expect("Cannot create resolver")
+    }
+
+    #[tokio::test]
+    async fn test_cache() {
+        let (url, requests) = serve_doh(CNAME_ANSWER, Duration::ZERO).await;
+        let resolver = get_resolver(url, Duration::from_secs(5), Duration::from_secs(60));
+
+        for _ in 0..2 {
+            let lookup = resolver
+                ._lookup("smp.example.com", AddressFamily::V4)
+                .await
+                .expect("Lookup failed");
+            assert_eq!(lookup.addresses, vec![parse_ip("93.184.215.14")]);
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_expiry() {
+        // the configured TTL caps the 60 seconds of the answer
+        let (url, requests) = serve_doh(CNAME_ANSWER, Duration::ZERO).await;
+        let resolver = get_resolver(url, Duration::from_secs(5), Duration::from_millis(100));
+
+        for _ in 0..2 {
+            resolver
+                ._lookup("smp.example.com", AddressFamily::V4)
+                .await
+                .expect("Lookup failed");
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        resolver
+            ._lookup("smp.example.com", AddressFamily::V4)
+            .await
+            .expect("Lookup failed");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_answer_ttl_caps_cache() {
+        let (url, requests) = serve_doh(
+            r#"{"Status":0,"Answer":[{"name":"smp.example.com.","type":1,"TTL":0,"data":"93.184.215.14"}]}"#,
+            Duration::ZERO,
+        )
+        .await;
+        let resolver = get_resolver(url, Duration::from_secs(5), Duration::from_secs(60));
+
+        for _ in 0..2 {
+            resolver
+                ._lookup("smp.example.com", AddressFamily::V4)
+                .await
+                .expect("Lookup failed");
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let (url, _) = serve_doh(CNAME_ANSWER, Duration::from_secs(5)).await;
+        let resolver = get_resolver(url, Duration::from_millis(100), Duration::from_secs(60));
+
+        let started = Instant::now();
+        assert!(resolver
+            ._lookup("smp.example.com", AddressFamily::V4)
+            .await
+            .is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        // failures aren't cached
+        assert!(resolver
+            .get_cached(&("smp.example.com".to_string(), RecordType::A))
+            .is_none());
+    }
+}
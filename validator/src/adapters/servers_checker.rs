@@ -1,44 +1,126 @@
-use crate::validator::ports::ServerCheckerPort;
-use std::time::Duration;
-use tungstenite::{connect, Message};
+use crate::{
+    adapters::chat_client::{send_command, Socket},
+    validator::{
+        domain_type::{parse_origin, Type},
+        ports::{CheckOutcome, ServerCheckerPort},
+        server_address::ServerAddress,
+    },
+};
+use tracing::error;
+use tungstenite::connect;
+
+/// SOCKS proxies are given as seen by the SMP client (`IP:port`, the client does not resolve
+/// names)
+pub struct ServersCheckerConfiguration {
+    pub smp_server_uri: String,
+    /// Proxy the client was started with, restored after I2P tests
+    pub tor_socks_proxy: Option<String>,
+    /// SOCKS mode the client was started with (`onion` or `always`), restored after I2P tests
+    pub socks_mode: String,
+    /// I2P router SOCKS proxy used while testing I2P-only servers
+    pub i2p_socks_proxy: Option<String>,
+}
 
 pub struct ServersChecker {
-    smp_server_uri: String,
+    config: ServersCheckerConfiguration,
 }
 
 impl ServersChecker {
-    pub fn new(smp_server_uri: String) -> Self {
-        Self { smp_server_uri }
+    pub fn new(config: ServersCheckerConfiguration) -> Self {
+        Self { config }
+    }
+
+    /// Tests the server with the client switched to another proxy. The client's network settings
+    /// are shared by every check, so the original proxy is restored even if the test failed.
+    async fn test_server_through(
+        &self,
+        socket: &mut Socket,
+        url: &str,
+        proxy: &str,
+        mode: &str,
+    ) -> Option<CheckOutcome> {
+        let restore_proxy = self.config.tor_socks_proxy.as_ref()?;
+        let outcome = match set_socks_proxy(socket, proxy, mode).await {
+            Some(()) => test_server(socket, url).await,
+            None => None,
+        };
+        let restored = match set_socks_proxy(socket, restore_proxy, &self.config.socks_mode).await {
+            Some(()) => Some(()),
+            // the test may have broken the connection, the settings live on in the client
+            None => match connect(&self.config.smp_server_uri) {
+                Ok((mut socket, _response)) => {
+                    set_socks_proxy(&mut socket, restore_proxy, &self.config.socks_mode).await
+                }
+                Err(_) => None,
+            },
+        };
+        if restored.is_none() {
+            error!(
+                proxy = restore_proxy,
+                "Failed to restore the SOCKS proxy of the SMP client"
+            );
+        }
+        outcome
     }
 }
 
+/// A server that requires a password rejects queue (SMP) or file (XFTP) creation with AUTH
+fn is_password_required(test_failure: &serde_json::Value) -> bool {
+    let step = test_failure["testStep"]
+        .as_str()
+        .unwrap_or_default()
+        .to_lowercase();
+    let error = &test_failure["testError"];
+    step.contains("create")
+        && (error["smpErr"]["type"] == "AUTH" || error["xftpErr"]["type"] == "AUTH")
+}
+
+fn get_domain_types(address: &ServerAddress) -> Vec<Type> {
+    address
+        .hosts
+        .iter()
+        .filter_map(|host| parse_origin(host).ok())
+        .map(|host| host.domain_type)
+        .collect()
+}
+
+async fn set_socks_proxy(socket: &mut Socket, proxy: &str, mode: &str) -> Option<()> {
+    let response =
+        send_command(socket, format!("/network socks={proxy} socks-mode={mode}")).await?;
+    (response["resp"]["type"] != "chatCmdError").then_some(())
+}
+
+async fn test_server(socket: &mut Socket, url: &str) -> Option<CheckOutcome> {
+    let response = send_command(socket, format!("/_server test 1 {}", url.trim())).await?;
+    let test_failure = &response["resp"]["testFailure"];
+    let outcome = if response["resp"]["type"] != "serverTestResult" {
+        CheckOutcome::Down
+    } else if test_failure.is_null() {
+        CheckOutcome::Up
+    } else if is_password_required(test_failure) {
+        CheckOutcome::PasswordRequired
+    } else {
+        CheckOutcome::Down
+    };
+    Some(outcome)
+}
+
 impl ServerCheckerPort for ServersChecker {
-    async fn check_server(&self, url: &str) -> Option<bool> {
-        let (mut socket, _response) = connect(&self.smp_server_uri).ok()?;
-        let corr_id = rand::random::<u32>().to_string();
-
-        let message = serde_json::json!({
-            "corrId": corr_id,
-            "cmd": format!("/_server test 1 {}", url.trim())
-        });
-
-        socket
-            .send(Message::Text(message.to_string().into()))
-            .ok()?;
-
-        while let Ok(msg) = socket.read() {
-            if let Message::Text(text) = msg {
-                if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
-                    if response["corrId"] == corr_id {
-                        let test_result = response["resp"]["type"] == "serverTestResult"
-                            && response["resp"]["testFailure"].is_null();
-                        return Some(test_result);
-                    }
-                }
-            }
-            tokio::time::sleep(Duration::from_millis(100)).await;
+    async fn check_server(&self, url: &str) -> Option<CheckOutcome> {
+        let (mut socket, _response) = connect(&self.config.smp_server_uri).ok()?;
+        let domain_types = ServerAddress::parse(url.trim())
+            .map(|address| get_domain_types(&address))
+            .unwrap_or_default();
+        let is_i2p_only =
+            !domain_types.is_empty() && domain_types.iter().all(|type_| *type_ == Type::I2p);
+
+        if !is_i2p_only {
+            return test_server(&mut socket, url).await;
         }
 
-        None
+        // the client reaches I2P only through the router's proxy, so it is switched for the test
+        let i2p_socks_proxy = self.config.i2p_socks_proxy.as_ref()?;
+        self.test_server_through(&mut socket, url, i2p_socks_proxy, "always")
+            .await
     }
 }
@@ -1,10 +1,29 @@
-use crate::validator::ports::{Server, ServerRepositoryPort, ServerStatus, ServerType};
-use log::info;
+use crate::validator::ports::{
+    CheckOutcome, HostStatus, Secret, Server, ServerRepositoryPort, ServerStatus, ServerType,
+    StoredStatus,
+};
+use chrono::{DateTime, Utc};
 pub use postgrest::Postgrest;
 use serde::{self, Deserialize, Serialize};
+use std::fmt::Debug;
+use std::net::IpAddr;
+use tracing::info;
 
 pub type DatabaseClient = Postgrest;
 
+/// Stored instead of a country for hosts that could not be geolocated
+const UNKNOWN_COUNTRY: &str = "unknown";
+
+fn to_stored_country(country: Option<&String>) -> String {
+    country
+        .cloned()
+        .unwrap_or_else(|| UNKNOWN_COUNTRY.to_string())
+}
+
+fn from_stored_country(country: Option<String>) -> Option<String> {
+    country.filter(|country| country != UNKNOWN_COUNTRY)
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 struct HostRow {
@@ -17,13 +36,67 @@ struct IdentityRow {
     pub identity: String,
 }
 
+/// PostgREST embeds a to-one relation as an object and a to-many relation as an array
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn iter(&self) -> std::slice::Iter<'_, T> {
+        match self {
+            OneOrMany::One(value) => std::slice::from_ref(value).iter(),
+            OneOrMany::Many(values) => values.iter(),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 struct ServerRow {
     pub uuid: String,
     pub protocol: i64,
+    #[serde(default)]
+    pub password: Option<Secret>,
     pub server_identities: IdentityRow,
-    pub server_hosts: HostRow,
+    pub server_hosts: OneOrMany<HostRow>,
+    #[serde(default)]
+    pub operator_contact_address: Option<String>,
+    /// Opt-in of the operator to messages about their server
+    #[serde(default)]
+    pub operator_notifications: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+struct StoredStatusRow {
+    pub status: bool,
+    /// Missing in rows stored before outcomes were recorded
+    pub outcome: Option<String>,
+    /// Missing in rows stored before servers were attributed the country of their aliases; their
+    /// `country` column may hold a network name instead, so such rows have an unknown country
+    pub server_country: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+struct OperatorNotificationRow {
+    pub created_at: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+struct NewOperatorNotificationRow {
+    pub server_uuid: String,
+    pub kind: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+struct DnsAddressesRow {
+    pub dns_addresses: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Debug)]
@@ -31,8 +104,65 @@ struct ServerRow {
 struct ServerStatusRow {
     pub server_uuid: String,
     pub status: bool,
-    pub country: Option<String>,
+    pub outcome: String,
+    /// Network of the first host; `None` if the address could not be tested
+    pub network: Option<String>,
+    /// Country of the server's own hosts
+    pub country: String,
+    /// Country of the server including its clearnet aliases, for overlay-only servers
+    pub server_country: String,
     pub info_page_available: bool,
+    pub server_version: Option<String>,
+    pub protocol_version_min: Option<u16>,
+    pub protocol_version_max: Option<u16>,
+    pub outdated: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+struct HostStatusRow {
+    pub server_uuid: String,
+    pub host: String,
+    pub status: bool,
+    pub outcome: String,
+    pub network: String,
+    pub country: String,
+    pub country_source: Option<String>,
+    pub info_page_available: bool,
+    pub info_page_url: Option<String>,
+    pub info_page_status_code: Option<u16>,
+    pub info_page_content_type: Option<String>,
+    pub info_page_body_outcome: Option<String>,
+    pub info_page_redirected_away: Option<bool>,
+    pub info_page_server_name: Option<String>,
+    pub info_page_operator: Option<String>,
+    pub info_page_contacts: Option<Vec<String>>,
+    pub info_page_source_code: Option<String>,
+    pub info_page_server_version: Option<String>,
+    pub info_page_hosting_country: Option<String>,
+    pub info_page_usage_conditions: Option<String>,
+    pub info_page_is_stock_template: Option<bool>,
+    pub tls_issuer: Option<String>,
+    pub tls_subject: Option<String>,
+    pub tls_subject_alt_names: Option<Vec<String>>,
+    pub tls_not_after: Option<String>,
+    pub tls_chain_valid: Option<bool>,
+    pub tls_hostname_matches: Option<bool>,
+    pub tls_expires_soon: Option<bool>,
+    pub port: Option<u16>,
+    pub port_state: Option<String>,
+    pub connect_time_ms: Option<u64>,
+    pub protocol_version_min: Option<u16>,
+    pub protocol_version_max: Option<u16>,
+    pub dns_resolves: Option<bool>,
+    pub dns_has_a: Option<bool>,
+    pub dns_has_aaaa: Option<bool>,
+    pub dns_cname_chain: Option<Vec<String>>,
+    pub dns_ttl: Option<u32>,
+    pub dns_addresses: Option<Vec<String>>,
+    pub dns_changed: Option<bool>,
+    pub ipv4_status: Option<bool>,
+    pub ipv6_status: Option<bool>,
 }
 
 pub struct ServersRepository {
@@ -52,7 +182,7 @@ impl ServersRepository {
         let response = self
             .client
             .from("servers")
-            .select("uuid,protocol,server_identities(identity),server_hosts(host)")
+            .select("uuid,protocol,password,operator_contact_address,operator_notifications,server_identities(identity),server_hosts(host)")
             .execute()
             .await
             .ok()?
@@ -63,15 +193,87 @@ impl ServersRepository {
         serde_json::from_str::<Vec<ServerRow>>(&response).ok()
     }
 
-    async fn update_server_status_row(&self, status: &ServerStatusRow) -> Option<()> {
+    async fn get_recent_status_rows(
+        &self,
+        server_id: &str,
+        count: usize,
+    ) -> Option<Vec<StoredStatusRow>> {
+        let response = self
+            .client
+            .from("server_statuses")
+            .select("status,outcome,server_country")
+            .eq("server_uuid", server_id)
+            .order("created_at.desc")
+            .limit(count)
+            .execute()
+            .await
+            .ok()?
+            .text()
+            .await
+            .ok()?;
+
+        serde_json::from_str::<Vec<StoredStatusRow>>(&response).ok()
+    }
+
+    async fn get_last_dns_addresses_row(
+        &self,
+        server_id: &str,
+        host: &str,
+    ) -> Option<DnsAddressesRow> {
+        let response = self
+            .client
+            .from("server_host_statuses")
+            .select("dns_addresses")
+            .eq("server_uuid", server_id)
+            .eq("host", host)
+            .order("created_at.desc")
+            .limit(1)
+            .execute()
+            .await
+            .ok()?
+            .text()
+            .await
+            .ok()?;
+
+        serde_json::from_str::<Vec<DnsAddressesRow>>(&response)
+            .ok()?
+            .pop()
+    }
+
+    async fn get_last_operator_notification_row(
+        &self,
+        server_id: &str,
+        kind: &str,
+    ) -> Option<OperatorNotificationRow> {
+        let response = self
+            .client
+            .from("operator_notifications")
+            .select("created_at")
+            .eq("server_uuid", server_id)
+            .eq("kind", kind)
+            .order("created_at.desc")
+            .limit(1)
+            .execute()
+            .await
+            .ok()?
+            .text()
+            .await
+            .ok()?;
+
+        serde_json::from_str::<Vec<OperatorNotificationRow>>(&response)
+            .ok()?
+            .pop()
+    }
+
+    async fn insert_rows<T: Serialize + Debug>(&self, table: &str, rows: &[T]) -> Option<()> {
         if self.is_dry {
-            info!("Dry run: would update server status {:?}", status);
+            info!(table, ?rows, "Dry run: would insert rows");
         } else {
             // Here you would implement the actual logic to update the server status in your database
-            info!("Updating server status {:?}", status);
+            info!(table, ?rows, "Inserting rows");
             self.client
-                .from("server_statuses")
-                .insert(serde_json::to_string(&[status]).ok()?)
+                .from(table)
+                .insert(serde_json::to_string(rows).ok()?)
                 .execute()
                 .await
                 .ok()?;
@@ -93,25 +295,161 @@ impl ServerRepositoryPort for ServersRepository {
                         _ => return None,
                     };
 
+                    let hosts: Vec<String> = row
+                        .server_hosts
+                        .iter()
+                        .map(|host| host.host.clone())
+                        .collect();
+
                     Some(Server {
                         type_,
                         id: row.uuid.clone(),
                         identity: row.server_identities.identity.clone(),
-                        host: row.server_hosts.host.clone(),
+                        password: row.password.clone(),
+                        hosts,
+                        operator_contact: row
+                            .operator_contact_address
+                            .clone()
+                            .filter(|_| row.operator_notifications),
                     })
                 })
                 .collect(),
         )
     }
 
-    async fn update_server_status(&self, server_id: &String, status: &ServerStatus) -> Option<()> {
+    async fn get_recent_statuses(
+        &self,
+        server_id: &str,
+        count: usize,
+    ) -> Option<Vec<StoredStatus>> {
+        let rows = self.get_recent_status_rows(server_id, count).await?;
+        Some(
+            rows.into_iter()
+                .map(|row| StoredStatus {
+                    outcome: match row.outcome.as_deref().and_then(CheckOutcome::parse) {
+                        Some(outcome) => outcome,
+                        None if row.status => CheckOutcome::Up,
+                        None => CheckOutcome::Down,
+                    },
+                    country: from_stored_country(row.server_country),
+                })
+                .collect(),
+        )
+    }
+
+    async fn get_last_operator_notification(
+        &self,
+        server_id: &str,
+        kind: &str,
+    ) -> Option<DateTime<Utc>> {
+        let row = self
+            .get_last_operator_notification_row(server_id, kind)
+            .await?;
+        DateTime::parse_from_rfc3339(&row.created_at)
+            .ok()
+            .map(|time| time.with_timezone(&Utc))
+    }
+
+    async fn record_operator_notification(&self, server_id: &str, kind: &str) -> Option<()> {
+        let row = NewOperatorNotificationRow {
+            server_uuid: server_id.to_string(),
+            kind: kind.to_string(),
+        };
+        self.insert_rows("operator_notifications", &[row]).await
+    }
+
+    async fn get_last_dns_addresses(&self, server_id: &str, host: &str) -> Option<Vec<IpAddr>> {
+        self.get_last_dns_addresses_row(server_id, host)
+            .await?
+            .dns_addresses?
+            .iter()
+            .map(|address| address.parse::<IpAddr>().ok())
+            .collect()
+    }
+
+    async fn update_server_status(&self, server_id: &str, status: &ServerStatus) -> Option<()> {
         let status_row = ServerStatusRow {
-            server_uuid: server_id.clone(),
-            status: status.status,
-            country: status.country.clone(),
+            server_uuid: server_id.to_string(),
+            status: status.outcome == CheckOutcome::Up,
+            outcome: status.outcome.as_str().to_string(),
+            network: status
+                .hosts
+                .first()
+                .map(|host| host.network.as_str().to_string()),
+            country: to_stored_country(status.hosts.iter().find_map(|host| host.country.as_ref())),
+            server_country: to_stored_country(status.country.as_ref()),
             info_page_available: status.info_page_available,
+            server_version: status.server_version.clone(),
+            protocol_version_min: status.protocol_versions.map(|versions| versions.min),
+            protocol_version_max: status.protocol_versions.map(|versions| versions.max),
+            outdated: status.outdated,
         };
-        self.update_server_status_row(&status_row).await?;
+        let host_rows: Vec<HostStatusRow> = status
+            .hosts
+            .iter()
+            .map(|host| to_host_status_row(server_id, host))
+            .collect();
+
+        self.insert_rows("server_statuses", &[status_row]).await?;
+        self.insert_rows("server_host_statuses", &host_rows).await?;
         Some(())
     }
 }
+
+fn to_host_status_row(server_id: &str, status: &HostStatus) -> HostStatusRow {
+    let dns = status.dns.as_ref();
+    let response = status.info_page.as_ref();
+    let info_page = response.and_then(|response| response.page.as_ref());
+    let tls = status.tls_certificate.as_ref();
+    let transport = status.transport.as_ref();
+    HostStatusRow {
+        server_uuid: server_id.to_string(),
+        host: status.host.clone(),
+        status: status.outcome == CheckOutcome::Up,
+        outcome: status.outcome.as_str().to_string(),
+        network: status.network.as_str().to_string(),
+        country: to_stored_country(status.country.as_ref()),
+        country_source: status.country_source.clone(),
+        info_page_available: info_page.is_some(),
+        info_page_url: response.map(|response| response.final_url.clone()),
+        info_page_status_code: response.map(|response| response.status_code),
+        info_page_content_type: response.and_then(|response| response.content_type.clone()),
+        info_page_body_outcome: response.map(|response| response.body.as_str().to_string()),
+        info_page_redirected_away: response.map(|response| response.redirected_away),
+        info_page_server_name: info_page.and_then(|page| page.server_name.clone()),
+        info_page_operator: info_page.and_then(|page| page.operator.clone()),
+        info_page_contacts: info_page.map(|page| page.contacts.clone()),
+        info_page_source_code: info_page.and_then(|page| page.source_code.clone()),
+        info_page_server_version: info_page.and_then(|page| page.server_version.clone()),
+        info_page_hosting_country: info_page.and_then(|page| page.hosting_country.clone()),
+        info_page_usage_conditions: info_page.and_then(|page| page.usage_conditions.clone()),
+        info_page_is_stock_template: info_page.map(|page| page.is_stock_template),
+        tls_issuer: tls.map(|tls| tls.issuer.clone()),
+        tls_subject: tls.map(|tls| tls.subject.clone()),
+        tls_subject_alt_names: tls.map(|tls| tls.subject_alt_names.clone()),
+        tls_not_after: tls.map(|tls| tls.not_after.to_rfc3339()),
+        tls_chain_valid: tls.map(|tls| tls.chain_valid),
+        tls_hostname_matches: tls.map(|tls| tls.hostname_matches),
+        tls_expires_soon: tls.map(|tls| tls.expires_soon),
+        port: transport.map(|transport| transport.port),
+        port_state: transport.map(|transport| transport.state.as_str().to_string()),
+        connect_time_ms: transport
+            .and_then(|transport| transport.connect_time)
+            .map(|time| time.as_millis() as u64),
+        protocol_version_min: transport
+            .and_then(|transport| transport.protocol_versions)
+            .map(|versions| versions.min),
+        protocol_version_max: transport
+            .and_then(|transport| transport.protocol_versions)
+            .map(|versions| versions.max),
+        dns_resolves: dns.map(|dns| dns.resolves),
+        dns_has_a: dns.map(|dns| dns.has_a),
+        dns_has_aaaa: dns.map(|dns| dns.has_aaaa),
+        dns_cname_chain: dns.map(|dns| dns.cname_chain.clone()),
+        dns_ttl: dns.and_then(|dns| dns.ttl),
+        dns_addresses: dns.map(|dns| dns.addresses.iter().map(|a| a.to_string()).collect()),
+        dns_changed: dns.and_then(|dns| dns.changed),
+        ipv4_status: status.ipv4_status,
+        ipv6_status: status.ipv6_status,
+    }
+}
@@ -0,0 +1,117 @@
+use crate::validator::ports::VersionRange;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::error::Error;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+/// Without this ALPN the server assumes a legacy client and offers only old versions
+const ALPN: &[u8] = b"smp/1";
+
+/// SMP servers present a self-signed certificate identified by the fingerprint in the server
+/// address. Only the versions are read from the handshake, so the certificate is not checked
+/// here; authenticating the server is up to the protocol test.
+#[derive(Debug)]
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        // servers sign with Ed25519 or Ed448 keys
+        vec![
+            SignatureScheme::ED25519,
+            SignatureScheme::ED448,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::RSA_PKCS1_SHA256,
+        ]
+    }
+}
+
+pub fn build_connector() -> Result<TlsConnector, Box<dyn Error>> {
+    let mut config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .dangerous()
+    .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+    .with_no_client_auth();
+    config.alpn_protocols = vec![ALPN.to_vec()];
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// The server handshake is the first block the server sends: a 2-byte content length, then
+/// the supported version range as two big-endian 16-bit numbers
+fn parse_server_handshake(block: &[u8]) -> Option<VersionRange> {
+    let length = u16::from_be_bytes([*block.first()?, *block.get(1)?]) as usize;
+    let content = block.get(2..2 + length.min(block.len() - 2))?;
+    let min = u16::from_be_bytes([*content.first()?, *content.get(1)?]);
+    let max = u16::from_be_bytes([*content.get(2)?, *content.get(3)?]);
+    (min <= max).then_some(VersionRange { min, max })
+}
+
+/// Opens a TLS session over an established connection and reads the versions the server offers
+pub async fn get_version_range(
+    connector: &TlsConnector,
+    stream: TcpStream,
+    host: &str,
+) -> Result<VersionRange, Box<dyn Error>> {
+    let server_name = ServerName::try_from(host.to_string())?;
+    let mut stream = connector.connect(server_name, stream).await?;
+    let mut block = [0u8; 6];
+    stream.read_exact(&mut block).await?;
+    parse_server_handshake(&block).ok_or_else(|| "Invalid server handshake".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_handshake() {
+        let block = [&[0, 5, 0, 6, 0, 15, 32][..], &[b'#'; 16][..]].concat();
+        assert_eq!(
+            parse_server_handshake(&block),
+            Some(VersionRange { min: 6, max: 15 })
+        );
+    }
+
+    #[test]
+    fn test_invalid_server_handshake() {
+        assert_eq!(parse_server_handshake(&[0, 3, 0, 6, 0]), None);
+        assert_eq!(parse_server_handshake(&[0, 4, 0, 9, 0, 6]), None);
+        assert_eq!(parse_server_handshake(&[0]), None);
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const CONNECT: u8 = 1;
+const DOMAIN_NAME: u8 = 3;
+
+/// Reply codes from RFC 1928
+pub const SUCCEEDED: u8 = 0;
+pub const CONNECTION_REFUSED: u8 = 5;
+
+#[derive(Debug)]
+pub enum SocksError {
+    Io(io::Error),
+    /// The proxy does not speak SOCKS5 or rejected our authentication
+    Handshake,
+    /// The proxy could not connect to the target; the code tells why
+    Reply(u8),
+}
+
+impl fmt::Display for SocksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocksError::Io(e) => write!(f, "SOCKS proxy I/O error: {}", e),
+            SocksError::Handshake => write!(f, "SOCKS proxy handshake failed"),
+            SocksError::Reply(code) => write!(f, "SOCKS proxy replied with code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for SocksError {}
+
+impl From<io::Error> for SocksError {
+    fn from(e: io::Error) -> Self {
+        SocksError::Io(e)
+    }
+}
+
+/// Username and password sent to the proxy; Tor uses distinct ones to isolate streams
+pub struct Credentials<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
+}
+
+fn greeting(credentials: Option<&Credentials>) -> Vec<u8> {
+    match credentials {
+        Some(_) => vec![VERSION, 1, USERNAME_PASSWORD],
+        None => vec![VERSION, 1, NO_AUTHENTICATION],
+    }
+}
+
+fn authentication(credentials: &Credentials) -> Result<Vec<u8>, SocksError> {
+    let username = u8::try_from(credentials.username.len()).map_err(|_| SocksError::Handshake)?;
+    let password = u8::try_from(credentials.password.len()).map_err(|_| SocksError::Handshake)?;
+    let mut request = vec![1, username];
+    request.extend_from_slice(credentials.username.as_bytes());
+    request.push(password);
+    request.extend_from_slice(credentials.password.as_bytes());
+    Ok(request)
+}
+
+/// The host is always sent as a name so the proxy resolves it, which onion and I2P hosts need
+fn connect_request(host: &str, port: u16) -> Result<Vec<u8>, SocksError> {
+    let length = u8::try_from(host.len()).map_err(|_| SocksError::Handshake)?;
+    let mut request = vec![VERSION, CONNECT, 0, DOMAIN_NAME, length];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+}
+
+/// Opens a stream to `host:port` through the SOCKS5 proxy at `proxy` (`ip:port` or `name:port`)
+pub async fn connect(
+    proxy: &str,
+    host: &str,
+    port: u16,
+    credentials: Option<&Credentials<'_>>,
+) -> Result<TcpStream, SocksError> {
+    let mut stream = TcpStream::connect(proxy).await?;
+
+    stream.write_all(&greeting(credentials)).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    match (choice, credentials) {
+        ([VERSION, NO_AUTHENTICATION], None) => {}
+        ([VERSION, USERNAME_PASSWORD], Some(credentials)) => {
+            stream.write_all(&authentication(credentials)?).await?;
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != SUCCEEDED {
+                return Err(SocksError::Handshake);
+            }
+        }
+        _ => return Err(SocksError::Handshake),
+    }
+
+    stream.write_all(&connect_request(host, port)?).await?;
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(SocksError::Handshake);
+    }
+    if reply[1] != SUCCEEDED {
+        return Err(SocksError::Reply(reply[1]));
+    }
+    // the bound address that follows is of no use to us, but must be consumed
+    let address_length = match reply[3] {
+        1 => 4,
+        4 => 16,
+        DOMAIN_NAME => stream.read_u8().await? as usize,
+        _ => return Err(SocksError::Handshake),
+    };
+    let mut bound = vec![0u8; address_length + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_request() {
+        assert_eq!(
+            connect_request("example.onion", 5223).expect("short host"),
+            [&[5, 1, 0, 3, 13][..], b"example.onion", &[0x14, 0x67][..]].concat()
+        );
+        assert!(connect_request(&"a".repeat(256), 443).is_err());
+    }
+
+    #[test]
+    fn test_authentication() {
+        let credentials = Credentials {
+            username: "host",
+            password: "1",
+        };
+        assert_eq!(greeting(Some(&credentials)), [5, 1, 2]);
+        assert_eq!(
+            authentication(&credentials).expect("short credentials"),
+            [1, 4, b'h', b'o', b's', b't', 1, b'1']
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use crate::validator::ports::TlsCertificate;
+use chrono::{DateTime, Utc};
+use std::error::Error;
+use std::net::IpAddr;
+use std::time::Duration;
+use x509_parser::extensions::GeneralName;
+use x509_parser::parse_x509_certificate;
+
+/// RFC 6125 matching: a leading `*` label covers exactly one label of the host
+fn matches_name(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.').to_lowercase();
+    let host = host.trim_end_matches('.').to_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest == suffix),
+        None => pattern == host,
+    }
+}
+
+fn decode_ip(encoded: &[u8]) -> Option<IpAddr> {
+    match encoded.len() {
+        4 => <[u8; 4]>::try_from(encoded).map(IpAddr::from).ok(),
+        16 => <[u8; 16]>::try_from(encoded).map(IpAddr::from).ok(),
+        _ => None,
+    }
+}
+
+/// Reads the leaf certificate a host presented. `chain_valid` comes from the caller, which
+/// knows whether the connection passed verification against the system roots.
+pub fn parse_certificate(
+    der: &[u8],
+    host: &str,
+    chain_valid: bool,
+    expiry_warning: Duration,
+    now: DateTime<Utc>,
+) -> Result<TlsCertificate, Box<dyn Error>> {
+    let (_, certificate) = parse_x509_certificate(der)?;
+
+    let mut subject_alt_names = vec![];
+    let mut hostname_matches = false;
+    if let Some(extension) = certificate.subject_alternative_name()? {
+        for name in &extension.value.general_names {
+            match name {
+                GeneralName::DNSName(dns) => {
+                    hostname_matches |= matches_name(dns, host);
+                    subject_alt_names.push(dns.to_string());
+                }
+                GeneralName::IPAddress(encoded) => {
+                    if let Some(ip) = decode_ip(encoded) {
+                        hostname_matches |= host.parse::<IpAddr>() == Ok(ip);
+                        subject_alt_names.push(ip.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    // the common name is only consulted by legacy clients when there are no SANs
+    if subject_alt_names.is_empty() {
+        hostname_matches = certificate
+            .subject()
+            .iter_common_name()
+            .filter_map(|name| name.as_str().ok())
+            .any(|name| matches_name(name, host));
+    }
+
+    let not_after = DateTime::from_timestamp(certificate.validity().not_after.timestamp(), 0)
+        .ok_or("Invalid certificate expiry date")?;
+    let warning = chrono::Duration::from_std(expiry_warning)?;
+
+    Ok(TlsCertificate {
+        issuer: certificate.issuer().to_string(),
+        subject: certificate.subject().to_string(),
+        subject_alt_names,
+        not_after,
+        chain_valid,
+        hostname_matches,
+        expires_soon: not_after - now <= warning,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_name() {
+        assert!(matches_name("smp.example.com", "SMP.example.com"));
+        assert!(!matches_name("smp.example.com", "xftp.example.com"));
+    }
+
+    #[test]
+    fn test_wildcard_name() {
+        assert!(matches_name("*.example.com", "smp.example.com"));
+        assert!(!matches_name("*.example.com", "example.com"));
+        assert!(!matches_name("*.example.com", "a.smp.example.com"));
+    }
+
+    #[test]
+    fn test_ip_address() {
+        assert_eq!(
+            decode_ip(&[192, 0, 2, 1]),
+            Some("192.0.2.1".parse().expect("valid address"))
+        );
+        assert_eq!(decode_ip(&[192, 0, 2]), None);
+    }
+
+    #[test]
+    fn test_invalid_certificate() {
+        assert!(parse_certificate(
+            b"not a certificate",
+            "example.com",
+            true,
+            Duration::ZERO,
+            Utc::now()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use crate::{
+    adapters::{
+        smp_handshake,
+        socks::{self, Credentials, SocksError},
+    },
+    validator::{
+        domain_type::{get_reserved_range, parse_origin, Host, Type},
+        ports::{PortState, ResolverPort, ServerType, TransportCheckerPort, TransportStatus},
+    },
+};
+use std::error::Error;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+pub struct TransportCheckerConfiguration {
+    /// Example: socks5h://localhost:9050
+    pub tor_socks5_proxy: String,
+    /// Gives every target host its own Tor circuits through distinct SOCKS credentials
+    pub isolate_tor_streams: bool,
+    pub timeout: Duration,
+}
+
+pub struct TransportChecker<R: ResolverPort> {
+    config: TransportCheckerConfiguration,
+    resolver: R,
+    /// `host:port` of the Tor proxy
+    tor_proxy: String,
+    isolation_nonce: String,
+    connector: TlsConnector,
+}
+
+/// A refused connection means the host is up but nothing listens on the port; anything that
+/// never got an answer looks like a firewall
+fn get_io_error_state(e: io::Error) -> Result<PortState, Box<dyn Error>> {
+    match e.kind() {
+        io::ErrorKind::ConnectionRefused => Ok(PortState::Closed),
+        io::ErrorKind::TimedOut
+        | io::ErrorKind::HostUnreachable
+        | io::ErrorKind::NetworkUnreachable => Ok(PortState::Filtered),
+        _ => Err(e.into()),
+    }
+}
+
+/// Errors talking to the proxy itself say nothing about the host, so only replies are mapped
+fn get_socks_error_state(e: SocksError) -> Result<PortState, Box<dyn Error>> {
+    match e {
+        SocksError::Reply(socks::CONNECTION_REFUSED) => Ok(PortState::Closed),
+        SocksError::Reply(_) => Ok(PortState::Filtered),
+        e => Err(e.into()),
+    }
+}
+
+/// Tries the addresses in order and returns the first connection; the error of the last
+/// address is kept so a refused connection still reads as a closed port
+async fn connect_any(addresses: &[SocketAddr]) -> Result<TcpStream, Box<dyn Error>> {
+    let mut last_error: Box<dyn Error> = "No address to connect to".into();
+    for address in addresses {
+        match TcpStream::connect(address).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e.into(),
+        }
+    }
+    Err(last_error)
+}
+
+impl<R: ResolverPort> TransportChecker<R> {
+    pub fn new(config: TransportCheckerConfiguration, resolver: R) -> Result<Self, Box<dyn Error>> {
+        let url = reqwest::Url::parse(&config.tor_socks5_proxy)?;
+        let tor_proxy = format!(
+            "{}:{}",
+            url.host_str().ok_or("Tor proxy has no host")?,
+            url.port().ok_or("Tor proxy has no port")?
+        );
+        Ok(Self {
+            config,
+            resolver,
+            tor_proxy,
+            isolation_nonce: rand::random::<u32>().to_string(),
+            connector: smp_handshake::build_connector()?,
+        })
+    }
+
+    /// Resolves through the validator's resolver rather than the system one, and never connects
+    /// to a non-public address a name resolves to
+    async fn get_addresses(
+        &self,
+        host: &Host,
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, Box<dyn Error>> {
+        let addresses = match host.value.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => self
+                .resolver
+                .resolve(&host.value)
+                .await
+                .ok_or_else(|| format!("Cannot resolve {}", host.value))?,
+        };
+        let public: Vec<SocketAddr> = addresses
+            .into_iter()
+            .filter(|ip| get_reserved_range(ip).is_none())
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect();
+        if public.is_empty() {
+            return Err(format!("{} has no public address", host.value).into());
+        }
+        Ok(public)
+    }
+
+    /// Connects through Tor for onion hosts and to `addresses` otherwise
+    async fn connect(
+        &self,
+        host: &Host,
+        port: u16,
+        addresses: &[SocketAddr],
+    ) -> Result<TcpStream, Box<dyn Error>> {
+        let stream = match host.domain_type {
+            Type::Onion => {
+                let credentials = Credentials {
+                    username: &host.value,
+                    password: &self.isolation_nonce,
+                };
+                let credentials = self.config.isolate_tor_streams.then_some(&credentials);
+                socks::connect(&self.tor_proxy, &host.value, port, credentials).await?
+            }
+            _ => connect_any(addresses).await?,
+        };
+        Ok(stream)
+    }
+
+    async fn _check_transport(
+        &self,
+        host: &str,
+        type_: ServerType,
+    ) -> Result<Option<TransportStatus>, Box<dyn Error>> {
+        let host_info = parse_origin(host)?;
+        // the I2P router only offers an HTTP proxy to the validator
+        if host_info.domain_type == Type::I2p {
+            return Ok(None);
+        }
+        let port = host_info.port.unwrap_or(type_.default_port());
+        // a host without a public address is not probed at all rather than reported as closed
+        let addresses = match host_info.domain_type {
+            Type::Onion => vec![],
+            _ => self.get_addresses(&host_info, port).await?,
+        };
+        self.probe(&host_info, port, &addresses, type_)
+            .await
+            .map(Some)
+    }
+
+    async fn probe(
+        &self,
+        host_info: &Host,
+        port: u16,
+        addresses: &[SocketAddr],
+        type_: ServerType,
+    ) -> Result<TransportStatus, Box<dyn Error>> {
+        let started = Instant::now();
+        let result = tokio::time::timeout(
+            self.config.timeout,
+            self.connect(host_info, port, addresses),
+        )
+        .await;
+        let connect_time = started.elapsed();
+
+        let (state, stream) = match result {
+            Err(_) => (PortState::Filtered, None),
+            Ok(Ok(stream)) => (PortState::Open, Some(stream)),
+            Ok(Err(e)) => match e.downcast::<SocksError>() {
+                Ok(e) => (get_socks_error_state(*e)?, None),
+                Err(e) => match e.downcast::<io::Error>() {
+                    Ok(e) => (get_io_error_state(*e)?, None),
+                    Err(e) => return Err(e),
+                },
+            },
+        };
+
+        // XFTP servers speak HTTP/2 and only send their handshake in response to a request, so
+        // only their port state is checked
+        let protocol_versions = match stream {
+            Some(stream) if type_ == ServerType::SMP => tokio::time::timeout(
+                self.config.timeout,
+                smp_handshake::get_version_range(&self.connector, stream, &host_info.value),
+            )
+            .await
+            .ok()
+            .and_then(Result::ok),
+            _ => None,
+        };
+        Ok(TransportStatus {
+            port,
+            state,
+            connect_time: (state == PortState::Open).then_some(connect_time),
+            protocol_versions,
+        })
+    }
+}
+
+impl<R: ResolverPort> TransportCheckerPort for TransportChecker<R> {
+    async fn check_transport(&self, host: &str, type_: ServerType) -> Option<TransportStatus> {
+        self._check_transport(host, type_).await.ok().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator::ports::{AddressFamily, DnsLookup};
+    use tokio::net::TcpListener;
+
+    /// Resolves every name to `addresses`; `None` is a failed lookup
+    struct FakeResolver {
+        addresses: Option<Vec<IpAddr>>,
+    }
+
+    impl ResolverPort for FakeResolver {
+        async fn resolve(&self, _host: &str) -> Option<Vec<IpAddr>> {
+            self.addresses.clone()
+        }
+
+        async fn lookup(&self, _host: &str, _family: AddressFamily) -> Option<DnsLookup> {
+            None
+        }
+    }
+
+    fn get_checker(addresses: Option<Vec<IpAddr>>) -> TransportChecker<FakeResolver> {
+        TransportChecker::new(
+            TransportCheckerConfiguration {
+                tor_socks5_proxy: "socks5h://127.0.0.1:9050".to_string(),
+                isolate_tor_streams: false,
+                timeout: Duration::from_secs(2),
+            },
+            FakeResolver { addresses },
+        )
+        .expect("Cannot create transport checker")
+    }
+
+    fn parse_ip(ip: &str) -> IpAddr {
+        ip.parse().expect("Invalid address")
+    }
+
+    #[tokio::test]
+    async fn test_skips_non_public_addresses() {
+        let checker = get_checker(Some(vec![parse_ip("127.0.0.1"), parse_ip("10.0.0.1")]));
+        assert!(checker
+            ._check_transport("smp.example.com", ServerType::SMP)
+            .await
+            .is_err());
+        assert!(checker
+            ._check_transport("192.168.1.1:5223", ServerType::SMP)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_skips_unresolvable_hosts() {
+        let checker = get_checker(None);
+        assert!(checker
+            ._check_transport("smp.example.com", ServerType::SMP)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_skips_i2p() {
+        let checker = get_checker(None);
+        let status = checker
+            ._check_transport(
+                "ub2ub7wtnpzcmvyj36ltypsoyjkiltsa5cbq4bkxcwvlvfhf4iqq.b32.i2p",
+                ServerType::SMP,
+            )
+            .await
+            .expect("I2P hosts are skipped");
+        assert!(status.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_uses_resolved_addresses() {
+        let checker = get_checker(Some(vec![parse_ip("93.184.215.14"), parse_ip("127.0.0.1")]));
+        let host_info = parse_origin("smp.example.com").expect("Invalid host");
+        let addresses = checker
+            .get_addresses(&host_info, 5223)
+            .await
+            .expect("A public address is left");
+        assert_eq!(
+            addresses,
+            [SocketAddr::new(parse_ip("93.184.215.14"), 5223)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Cannot bind");
+        let address = listener.local_addr().expect("Bound address");
+        // the SMP handshake fails as soon as the connection is closed
+        tokio::spawn(async move { drop(listener.accept().await) });
+
+        let checker = get_checker(None);
+        let host_info = parse_origin(&address.to_string()).expect("Invalid host");
+        let status = checker
+            .probe(&host_info, address.port(), &[address], ServerType::SMP)
+            .await
+            .expect("Probed");
+        assert_eq!(status.state, PortState::Open);
+        assert!(status.connect_time.is_some());
+        assert!(status.protocol_versions.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_closed_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Cannot bind");
+        let address = listener.local_addr().expect("Bound address");
+        drop(listener);
+
+        let checker = get_checker(None);
+        let host_info = parse_origin(&address.to_string()).expect("Invalid host");
+        let status = checker
+            .probe(&host_info, address.port(), &[address], ServerType::XFTP)
+            .await
+            .expect("Probed");
+        assert_eq!(status.state, PortState::Closed);
+        assert!(status.connect_time.is_none());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::validator::ports::{NotifierPort, Secret, StatusEvent, StatusEventKind};
+use chrono::Utc;
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::error::Error;
+use std::time::Duration;
+use tracing::info;
+
+/// Header carrying `sha256=<hex HMAC-SHA256 of the body>`, as GitHub signs its webhooks
+const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+pub struct WebhookConfiguration {
+    pub urls: Vec<String>,
+    /// Key the bodies are signed with; unsigned if `None`
+    pub secret: Option<Secret>,
+    pub timeout: Duration,
+    /// Logs the events instead of sending them
+    pub is_dry: bool,
+}
+
+pub struct Webhook {
+    config: WebhookConfiguration,
+    client: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: &'static str,
+    server_id: &'a str,
+    /// Included in the signed body so a captured request can't be replayed later unnoticed
+    timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<&'a str>,
+}
+
+fn to_payload(event: &StatusEvent) -> Payload<'_> {
+    let (previous, current) = match &event.kind {
+        StatusEventKind::WentDown => (Some("up"), Some("down")),
+        StatusEventKind::WentUp => (Some("down"), Some("up")),
+        StatusEventKind::CountryChanged { previous, current } => {
+            (Some(previous.as_str()), Some(current.as_str()))
+        }
+    };
+    Payload {
+        event: event.kind.as_str(),
+        server_id: &event.server_id,
+        timestamp: Utc::now().to_rfc3339(),
+        previous,
+        current,
+    }
+}
+
+fn sign(secret: &Secret, body: &[u8]) -> Result<String, Box<dyn Error>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose().as_bytes())?;
+    mac.update(body);
+    Ok(format!(
+        "sha256={}",
+        HEXLOWER.encode(&mac.finalize().into_bytes())
+    ))
+}
+
+impl Webhook {
+    pub fn new(config: WebhookConfiguration) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::builder().timeout(config.timeout).build()?;
+        Ok(Self { config, client })
+    }
+
+    async fn send(&self, url: &str, body: &str) -> Result<(), Box<dyn Error>> {
+        let mut request = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        if let Some(secret) = &self.config.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, body.as_bytes())?);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn _notify(&self, event: &StatusEvent) -> Result<(), Box<dyn Error>> {
+        if self.config.urls.is_empty() {
+            return Ok(());
+        }
+        let body = serde_json::to_string(&to_payload(event))?;
+        if self.config.is_dry {
+            info!(body, "Dry run: would send to webhooks");
+            return Ok(());
+        }
+        // every hook gets the event even if an earlier one fails
+        let mut result = Ok(());
+        for url in &self.config.urls {
+            if let Err(e) = self.send(url, &body).await {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+impl NotifierPort for Webhook {
+    async fn notify(&self, event: &StatusEvent) -> Option<()> {
+        self._notify(event).await.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // test vector from the GitHub webhook documentation
+        let secret = Secret::new("It's a Secret to Everybody".to_string());
+        assert_eq!(
+            sign(&secret, b"Hello, World!").expect("valid key"),
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        );
+    }
+
+    #[test]
+    fn test_country_payload() {
+        let event = StatusEvent {
+            server_id: "a".to_string(),
+            kind: StatusEventKind::CountryChanged {
+                previous: "DE".to_string(),
+                current: "NL".to_string(),
+            },
+        };
+        let payload = to_payload(&event);
+        assert_eq!(payload.event, "country_changed");
+        assert_eq!(
+            (payload.previous, payload.current),
+            (Some("DE"), Some("NL"))
+        );
+    }
+}
@@ -1,41 +1,116 @@
 extern crate chrono;
-extern crate env_logger;
-extern crate log;
 
 pub mod adapters;
 pub mod validator;
 
-use chrono::Local;
 use clap::{parser::ValueSource, value_parser, Arg, ArgAction, Command};
-use env_logger::Builder;
-use log::{info, LevelFilter};
-use std::io::Write;
-
-use crate::adapters::http_checker;
-
-pub fn init_logger() {
-    Builder::new()
-        .format(|buf, record| {
-            writeln!(
-                buf,
-                "{} [{}] - {}",
-                Local::now().format("%Y-%m-%dT%H:%M:%S"),
-                record.level(),
-                record.args()
-            )
-        })
-        .filter(None, LevelFilter::Info)
-        .init();
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+use tracing_subscriber::{fmt::time::ChronoLocal, EnvFilter};
+
+use crate::adapters::{
+    chat_messenger, dns_checker, geoip, http_checker, metrics, resolver, servers_checker,
+    transport_checker, webhook,
+};
+use crate::validator::{
+    ports::Secret, summary::RunSummary, version::SoftwareVersion, OperatorNotificationSettings,
+};
+
+/// Records of dependencies that log through `log` are forwarded to the subscriber, so they carry
+/// the fields of the spans they are logged in. Without a level, `RUST_LOG` applies, then `info`.
+pub fn init_logger(level: Option<&str>, json: bool) {
+    let filter = match level {
+        Some(level) => EnvFilter::new(level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_timer(ChronoLocal::new("%Y-%m-%dT%H:%M:%S".to_string()));
+    if json {
+        subscriber
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init();
+    } else {
+        subscriber
+            .with_target(false)
+            .with_ansi(std::io::stdout().is_terminal())
+            .init();
+    }
 }
 
 struct Args {
     smp_server_uri: String,
     dry: bool,
+    log_level: Option<String>,
+    log_json: bool,
+    daemon: bool,
+    check_interval: u64,
+    metrics_address: String,
+    summary_json: Option<String>,
+    summary_markdown: Option<String>,
     retry_count: u32,
     maxmind_db_path: String,
     supabase_url: String,
     supabase_key: String,
+    webhook_urls: Vec<String>,
+    webhook_secret: Option<String>,
+    status_change_debounce: usize,
+    notify_operators: bool,
+    operator_failing_checks: usize,
+    operator_notification_interval: u64,
+    operator_notification_limit: usize,
     tor_socks5_proxy: String,
+    i2p_proxy: Option<String>,
+    smp_client_i2p_socks_proxy: Option<String>,
+    smp_client_tor_socks_proxy: Option<String>,
+    smp_client_socks_mode: String,
+    tor_stream_isolation: bool,
+    min_server_version: Option<SoftwareVersion>,
+    min_smp_version: Option<u16>,
+    tls_expiry_warning_days: u64,
+    info_page_schemes: Vec<String>,
+    info_page_max_redirects: usize,
+    http_timeout: u64,
+    connect_timeout: u64,
+    http_user_agent: String,
+    http_max_body_size: usize,
+    info_page_content_types: Vec<String>,
+    dns_servers: Vec<SocketAddr>,
+    dns_over_https_url: Option<String>,
+    dns_over_https_via_tor: bool,
+    dns_timeout: u64,
+    dns_cache_ttl: u64,
+    maxmind_db_max_age: u64,
+    maxmind_db_refuse_stale: bool,
+    maxmind_db_update_url: Option<String>,
+    maxmind_db_sha256_url: Option<String>,
+    maxmind_db_cache_dir: String,
+    maxmind_db_update_via_tor: bool,
+    maxmind_db_update_timeout: u64,
+    maxmind_db_max_size: usize,
+    geoip_mmdb: Vec<(String, String)>,
+    geoip_csv: Vec<(String, String)>,
+    geoip_overrides: Option<String>,
+}
+
+fn parse_named_path(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, path)) if !name.is_empty() && !path.is_empty() => {
+            Ok((name.to_string(), path.to_string()))
+        }
+        _ => Err("expected NAME=PATH".to_string()),
+    }
+}
+
+fn parse_dns_server(value: &str) -> Result<SocketAddr, String> {
+    resolver::parse_name_server(value).map_err(|e| e.to_string())
 }
 
 fn parse_args() -> Args {
@@ -49,6 +124,101 @@ fn parse_args() -> Args {
                 .num_args(1)
                 .required(true),
         )
+        .arg(
+            Arg::new("maxmind-db-max-age")
+                .long("maxmind-db-max-age")
+                .value_name("DAYS")
+                .help("Sets the age after which the MaxMind database is considered stale")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("30"),
+        )
+        .arg(
+            Arg::new("maxmind-db-refuse-stale")
+                .long("maxmind-db-refuse-stale")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Skips runs instead of warning while the MaxMind database is stale and cannot be refreshed"),
+        )
+        .arg(
+            Arg::new("maxmind-db-update-url")
+                .long("maxmind-db-update-url")
+                .value_name("URL")
+                .help("Sets the URL to download a fresh MaxMind database from when the current one is stale")
+                .num_args(1)
+                .required(false),
+        )
+        .arg(
+            Arg::new("maxmind-db-sha256-url")
+                .long("maxmind-db-sha256-url")
+                .value_name("URL")
+                .help("Sets the URL of the SHA-256 checksum of the downloaded MaxMind database")
+                .num_args(1)
+                .requires("maxmind-db-update-url")
+                .required(false),
+        )
+        .arg(
+            Arg::new("maxmind-db-cache-dir")
+                .long("maxmind-db-cache-dir")
+                .value_name("DIR")
+                .help("Sets the directory where the downloaded MaxMind database is stored")
+                .num_args(1)
+                .default_value("geoip-cache"),
+        )
+        .arg(
+            Arg::new("maxmind-db-update-via-tor")
+                .long("maxmind-db-update-via-tor")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .requires("maxmind-db-update-url")
+                .help("Downloads the MaxMind database through the Tor SOCKS5 proxy"),
+        )
+        .arg(
+            Arg::new("maxmind-db-update-timeout")
+                .long("maxmind-db-update-timeout")
+                .value_name("SECONDS")
+                .help("Sets the timeout of the MaxMind database download")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("300"),
+        )
+        .arg(
+            Arg::new("maxmind-db-max-size")
+                .long("maxmind-db-max-size")
+                .value_name("BYTES")
+                .help("Sets the maximum size of a downloaded MaxMind database")
+                .num_args(1)
+                .value_parser(value_parser!(usize))
+                .default_value("134217728"),
+        )
+        .arg(
+            Arg::new("geoip-mmdb")
+                .long("geoip-mmdb")
+                .value_name("NAME=FILE")
+                .help("Adds a fallback GeoIP database in MaxMind DB format (e.g. DB-IP or IP2Location). Can be repeated")
+                .num_args(1)
+                .value_parser(parse_named_path)
+                .action(ArgAction::Append)
+                .required(false),
+        )
+        .arg(
+            Arg::new("geoip-csv")
+                .long("geoip-csv")
+                .value_name("NAME=FILE")
+                .help("Adds a fallback GeoIP CSV file with start,end,country IP ranges. Can be repeated")
+                .num_args(1)
+                .value_parser(parse_named_path)
+                .action(ArgAction::Append)
+                .required(false),
+        )
+        .arg(
+            Arg::new("geoip-overrides")
+                .long("geoip-overrides")
+                .value_name("FILE")
+                .help("Sets the JSON file mapping hosts to countries declared by their operators. Takes precedence over GeoIP databases")
+                .num_args(1)
+                .required(false),
+        )
         .arg(
             Arg::new("smp-client-ws-url")
                 .long("smp-client-ws-url")
@@ -64,6 +234,64 @@ fn parse_args() -> Args {
                 .action(ArgAction::SetTrue)
                 .help("Dry run mode. No changes will be made to the database."),
         )
+        .arg(
+            Arg::new("log-level")
+                .long("log-level")
+                .value_name("LEVEL")
+                .help("Sets the log level; overrides RUST_LOG [default: info]")
+                .num_args(1)
+                .value_parser(["error", "warn", "info", "debug", "trace"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("Sets the log output format")
+                .num_args(1)
+                .value_parser(["text", "json"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::new("daemon")
+                .long("daemon")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Keeps running, checking the servers periodically and serving metrics"),
+        )
+        .arg(
+            Arg::new("check-interval")
+                .long("check-interval")
+                .value_name("MINUTES")
+                .help("Sets the time between the end of a run and the start of the next one in daemon mode")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("60"),
+        )
+        .arg(
+            Arg::new("metrics-address")
+                .long("metrics-address")
+                .value_name("ADDRESS")
+                .help("Sets the address the Prometheus /metrics endpoint listens on in daemon mode")
+                .num_args(1)
+                .default_value("0.0.0.0:9100"),
+        )
+        .arg(
+            Arg::new("summary-json")
+                .long("summary-json")
+                .value_name("PATH")
+                .help("Writes the summary of each run to this file as JSON")
+                .num_args(1)
+                .required(false),
+        )
+        .arg(
+            Arg::new("summary-markdown")
+                .long("summary-markdown")
+                .value_name("PATH")
+                .help("Appends the summary of each run to this file as Markdown. Example: $GITHUB_STEP_SUMMARY")
+                .num_args(1)
+                .required(false),
+        )
         .arg(
             Arg::new("retry-count")
                 .long("retry-count")
@@ -81,6 +309,137 @@ fn parse_args() -> Args {
                 .num_args(1)
                 .required(true),
         )
+        .arg(
+            Arg::new("i2p-proxy")
+                .long("i2p-proxy")
+                .value_name("URL")
+                .help("Sets the I2P router proxy used to fetch info pages of I2P hosts. Example: http://localhost:4444")
+                .num_args(1)
+                .required(false),
+        )
+        .arg(
+            Arg::new("smp-client-i2p-socks-proxy")
+                .long("smp-client-i2p-socks-proxy")
+                .value_name("ADDRESS")
+                .help("Sets the I2P SOCKS proxy the SMP client switches to when testing I2P-only servers. Example: 172.17.0.3:4447")
+                .num_args(1)
+                .requires("smp-client-tor-socks-proxy")
+                .required(false),
+        )
+        .arg(
+            Arg::new("smp-client-tor-socks-proxy")
+                .long("smp-client-tor-socks-proxy")
+                .value_name("ADDRESS")
+                .help("Sets the Tor SOCKS proxy the SMP client was started with, as the client sees it, to restore it after testing I2P-only servers. Example: 172.17.0.2:9050")
+                .num_args(1)
+                .required(false),
+        )
+        .arg(
+            Arg::new("smp-client-socks-mode")
+                .long("smp-client-socks-mode")
+                .value_name("MODE")
+                .help("Sets the SOCKS mode the SMP client was started with, restored after switching proxies")
+                .num_args(1)
+                .value_parser(["onion", "always"])
+                .default_value("onion"),
+        )
+        .arg(
+            Arg::new("tor-stream-isolation")
+                .long("tor-stream-isolation")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Uses separate Tor circuits per checked host for info page and port checks. SMP tests share the circuits of the SMP client"),
+        )
+        .arg(
+            Arg::new("min-server-version")
+                .long("min-server-version")
+                .value_name("VERSION")
+                .help("Flags servers whose info page shows an older software version, pre-releases counting as older than their release. Example: 6.3.0")
+                .num_args(1)
+                .value_parser(value_parser!(SoftwareVersion))
+                .required(false),
+        )
+        .arg(
+            Arg::new("min-smp-version")
+                .long("min-smp-version")
+                .value_name("VERSION")
+                .help("Flags SMP servers whose handshake offers no protocol version at or above this one; XFTP protocol versions are not detected")
+                .num_args(1)
+                .value_parser(value_parser!(u16))
+                .required(false),
+        )
+        .arg(
+            Arg::new("tls-expiry-warning-days")
+                .long("tls-expiry-warning-days")
+                .value_name("DAYS")
+                .help("Flags TLS certificates of info pages that expire within this many days")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("14"),
+        )
+        .arg(
+            Arg::new("info-page-schemes")
+                .long("info-page-schemes")
+                .value_name("SCHEMES")
+                .help("Sets the comma-separated order of schemes tried for info pages of clearnet hosts")
+                .num_args(1)
+                .value_delimiter(',')
+                .value_parser(["https", "http"])
+                .default_value("https,http"),
+        )
+        .arg(
+            Arg::new("info-page-max-redirects")
+                .long("info-page-max-redirects")
+                .value_name("COUNT")
+                .help("Sets the maximum number of redirects followed when fetching info pages")
+                .num_args(1)
+                .value_parser(value_parser!(usize))
+                .default_value("5"),
+        )
+        .arg(
+            Arg::new("http-timeout")
+                .long("http-timeout")
+                .value_name("SECONDS")
+                .help("Sets the timeout of info page and TLS certificate requests")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("5"),
+        )
+        .arg(
+            Arg::new("connect-timeout")
+                .long("connect-timeout")
+                .value_name("SECONDS")
+                .help("Sets the timeout of plain TCP connections to the server ports")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("10"),
+        )
+        .arg(
+            Arg::new("http-user-agent")
+                .long("http-user-agent")
+                .value_name("USER_AGENT")
+                .help("Sets the User-Agent header of info page requests")
+                .num_args(1)
+                .default_value(concat!("simplex-catalog-servers-validator/", env!("CARGO_PKG_VERSION"))),
+        )
+        .arg(
+            Arg::new("http-max-body-size")
+                .long("http-max-body-size")
+                .value_name("BYTES")
+                .help("Sets the maximum size of an info page body")
+                .num_args(1)
+                .value_parser(value_parser!(usize))
+                .default_value("1048576"),
+        )
+        .arg(
+            Arg::new("info-page-content-types")
+                .long("info-page-content-types")
+                .value_name("TYPES")
+                .help("Sets the comma-separated media types of info pages whose bodies are read")
+                .num_args(1)
+                .value_delimiter(',')
+                .default_value("text/html,text/plain,application/xhtml+xml"),
+        )
         .arg(
             Arg::new("supabase-url")
                 .long("supabase-url")
@@ -97,12 +456,132 @@ fn parse_args() -> Args {
                 .num_args(1)
                 .required(true),
         )
+        .arg(
+            Arg::new("webhook-url")
+                .long("webhook-url")
+                .value_name("URL")
+                .help("Adds a webhook that receives status changes of servers as JSON. Can be repeated")
+                .num_args(1)
+                .action(ArgAction::Append)
+                .required(false),
+        )
+        .arg(
+            Arg::new("webhook-secret")
+                .long("webhook-secret")
+                .value_name("SECRET")
+                .help("Signs webhook bodies with HMAC-SHA256 in the X-Signature-256 header. Prefer the environment variable, as arguments are visible to other processes")
+                .num_args(1)
+                .env("WEBHOOK_SECRET")
+                .hide_env_values(true)
+                .required(false),
+        )
+        .arg(
+            Arg::new("status-change-debounce")
+                .long("status-change-debounce")
+                .value_name("CHECKS")
+                .help("Reports a status change only once it has held for this many consecutive checks")
+                .num_args(1)
+                .value_parser(value_parser!(usize))
+                .default_value("2"),
+        )
+        .arg(
+            Arg::new("notify-operators")
+                .long("notify-operators")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Messages operators who opted in through the SMP client when their server starts failing, recovers or its certificate expires soon"),
+        )
+        .arg(
+            Arg::new("operator-failing-checks")
+                .long("operator-failing-checks")
+                .value_name("CHECKS")
+                .help("Consecutive failed checks before the operator is notified")
+                .num_args(1)
+                .value_parser(value_parser!(usize))
+                .default_value("3"),
+        )
+        .arg(
+            Arg::new("operator-notification-interval")
+                .long("operator-notification-interval")
+                .value_name("HOURS")
+                .help("Minimum time between two notifications of the same kind about a server")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("24"),
+        )
+        .arg(
+            Arg::new("operator-notification-limit")
+                .long("operator-notification-limit")
+                .value_name("MESSAGES")
+                .help("Maximum number of operator notifications sent per run")
+                .num_args(1)
+                .value_parser(value_parser!(usize))
+                .default_value("10"),
+        )
+        .arg(
+            Arg::new("dns-server")
+                .long("dns-server")
+                .value_name("ADDRESS")
+                .help("Adds an upstream DNS server (IP or IP:port). Can be repeated. Uses the system resolver configuration if omitted")
+                .num_args(1)
+                .value_parser(parse_dns_server)
+                .action(ArgAction::Append)
+                .required(false),
+        )
+        .arg(
+            Arg::new("dns-over-https-url")
+                .long("dns-over-https-url")
+                .value_name("URL")
+                .help("Resolves hosts using a DNS-over-HTTPS JSON API instead of plain DNS. Example: https://cloudflare-dns.com/dns-query")
+                .num_args(1)
+                .conflicts_with("dns-server")
+                .required(false),
+        )
+        .arg(
+            Arg::new("dns-over-https-via-tor")
+                .long("dns-over-https-via-tor")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .requires("dns-over-https-url")
+                .help("Sends DNS-over-HTTPS requests through the Tor SOCKS5 proxy"),
+        )
+        .arg(
+            Arg::new("dns-timeout")
+                .long("dns-timeout")
+                .value_name("SECONDS")
+                .help("Sets the DNS resolution timeout")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("5"),
+        )
+        .arg(
+            Arg::new("dns-cache-ttl")
+                .long("dns-cache-ttl")
+                .value_name("SECONDS")
+                .help("Sets the maximum time a DNS answer is cached")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("300"),
+        )
         .get_matches();
 
     let smp_server_uri = command
         .get_one::<String>("smp-client-ws-url")
         .expect("required argument");
     let dry = command.value_source("dry") == Some(ValueSource::CommandLine);
+    let log_level = command.get_one::<String>("log-level").cloned();
+    let log_json = command
+        .get_one::<String>("log-format")
+        .is_some_and(|format| format == "json");
+    let daemon = command.value_source("daemon") == Some(ValueSource::CommandLine);
+    let check_interval = *command
+        .get_one::<u64>("check-interval")
+        .expect("argument with default value");
+    let metrics_address = command
+        .get_one::<String>("metrics-address")
+        .expect("argument with default value");
+    let summary_json = command.get_one::<String>("summary-json").cloned();
+    let summary_markdown = command.get_one::<String>("summary-markdown").cloned();
     let retry_count = *command
         .get_one::<u32>("retry-count")
         .expect("required argument");
@@ -112,30 +591,270 @@ fn parse_args() -> Args {
     let supabase_key = command
         .get_one::<String>("supabase-key")
         .expect("required argument");
+    let webhook_urls = command
+        .get_many::<String>("webhook-url")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    // Docker and .env set the variable even when it's left empty
+    let webhook_secret = command
+        .get_one::<String>("webhook-secret")
+        .filter(|secret| !secret.is_empty())
+        .cloned();
+    let status_change_debounce = *command
+        .get_one::<usize>("status-change-debounce")
+        .expect("argument with default value");
+    let notify_operators =
+        command.value_source("notify-operators") == Some(ValueSource::CommandLine);
+    let operator_failing_checks = *command
+        .get_one::<usize>("operator-failing-checks")
+        .expect("argument with default value");
+    let operator_notification_interval = *command
+        .get_one::<u64>("operator-notification-interval")
+        .expect("argument with default value");
+    let operator_notification_limit = *command
+        .get_one::<usize>("operator-notification-limit")
+        .expect("argument with default value");
     let maxmind_db_path = command
         .get_one::<String>("maxmind-db-path")
         .expect("required argument");
     let tor_socks5_proxy = command
         .get_one::<String>("tor-socks5-proxy")
         .expect("required argument");
+    let i2p_proxy = command.get_one::<String>("i2p-proxy").cloned();
+    let smp_client_i2p_socks_proxy = command
+        .get_one::<String>("smp-client-i2p-socks-proxy")
+        .cloned();
+    let smp_client_tor_socks_proxy = command
+        .get_one::<String>("smp-client-tor-socks-proxy")
+        .cloned();
+    let smp_client_socks_mode = command
+        .get_one::<String>("smp-client-socks-mode")
+        .expect("argument with default value")
+        .clone();
+    let tor_stream_isolation =
+        command.value_source("tor-stream-isolation") == Some(ValueSource::CommandLine);
+    let min_server_version = command
+        .get_one::<SoftwareVersion>("min-server-version")
+        .cloned();
+    let min_smp_version = command.get_one::<u16>("min-smp-version").copied();
+    let tls_expiry_warning_days = *command
+        .get_one::<u64>("tls-expiry-warning-days")
+        .expect("argument with default value");
+    let info_page_schemes = command
+        .get_many::<String>("info-page-schemes")
+        .expect("argument with default value")
+        .cloned()
+        .collect();
+    let info_page_max_redirects = *command
+        .get_one::<usize>("info-page-max-redirects")
+        .expect("argument with default value");
+    let http_timeout = *command
+        .get_one::<u64>("http-timeout")
+        .expect("argument with default value");
+    let connect_timeout = *command
+        .get_one::<u64>("connect-timeout")
+        .expect("argument with default value");
+    let http_user_agent = command
+        .get_one::<String>("http-user-agent")
+        .expect("argument with default value");
+    let http_max_body_size = *command
+        .get_one::<usize>("http-max-body-size")
+        .expect("argument with default value");
+    let info_page_content_types = command
+        .get_many::<String>("info-page-content-types")
+        .expect("argument with default value")
+        .cloned()
+        .collect();
+    let dns_servers = command
+        .get_many::<SocketAddr>("dns-server")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    let dns_over_https_url = command.get_one::<String>("dns-over-https-url").cloned();
+    let dns_over_https_via_tor =
+        command.value_source("dns-over-https-via-tor") == Some(ValueSource::CommandLine);
+    let dns_timeout = *command
+        .get_one::<u64>("dns-timeout")
+        .expect("argument with default value");
+    let dns_cache_ttl = *command
+        .get_one::<u64>("dns-cache-ttl")
+        .expect("argument with default value");
+    let maxmind_db_max_age = *command
+        .get_one::<u64>("maxmind-db-max-age")
+        .expect("argument with default value");
+    let maxmind_db_refuse_stale =
+        command.value_source("maxmind-db-refuse-stale") == Some(ValueSource::CommandLine);
+    let maxmind_db_update_url = command.get_one::<String>("maxmind-db-update-url").cloned();
+    let maxmind_db_sha256_url = command.get_one::<String>("maxmind-db-sha256-url").cloned();
+    let maxmind_db_cache_dir = command
+        .get_one::<String>("maxmind-db-cache-dir")
+        .expect("argument with default value");
+    let maxmind_db_update_via_tor =
+        command.value_source("maxmind-db-update-via-tor") == Some(ValueSource::CommandLine);
+    let maxmind_db_update_timeout = *command
+        .get_one::<u64>("maxmind-db-update-timeout")
+        .expect("argument with default value");
+    let maxmind_db_max_size = *command
+        .get_one::<usize>("maxmind-db-max-size")
+        .expect("argument with default value");
+    let geoip_mmdb = command
+        .get_many::<(String, String)>("geoip-mmdb")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    let geoip_csv = command
+        .get_many::<(String, String)>("geoip-csv")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    let geoip_overrides = command.get_one::<String>("geoip-overrides").cloned();
 
     Args {
         smp_server_uri: smp_server_uri.clone(),
         dry,
+        log_level,
+        log_json,
+        daemon,
+        check_interval,
+        metrics_address: metrics_address.clone(),
+        summary_json,
+        summary_markdown,
         retry_count,
         supabase_url: supabase_url.clone(),
         supabase_key: supabase_key.clone(),
+        webhook_urls,
+        webhook_secret,
+        status_change_debounce,
+        notify_operators,
+        operator_failing_checks,
+        operator_notification_interval,
+        operator_notification_limit,
         maxmind_db_path: maxmind_db_path.clone(),
         tor_socks5_proxy: tor_socks5_proxy.clone(),
+        i2p_proxy,
+        smp_client_i2p_socks_proxy,
+        smp_client_tor_socks_proxy,
+        smp_client_socks_mode,
+        tor_stream_isolation,
+        min_server_version,
+        min_smp_version,
+        tls_expiry_warning_days,
+        info_page_schemes,
+        info_page_max_redirects,
+        http_timeout,
+        connect_timeout,
+        http_user_agent: http_user_agent.clone(),
+        http_max_body_size,
+        info_page_content_types,
+        dns_servers,
+        dns_over_https_url,
+        dns_over_https_via_tor,
+        dns_timeout,
+        dns_cache_ttl,
+        maxmind_db_max_age,
+        maxmind_db_refuse_stale,
+        maxmind_db_update_url,
+        maxmind_db_sha256_url,
+        maxmind_db_cache_dir: maxmind_db_cache_dir.clone(),
+        maxmind_db_update_via_tor,
+        maxmind_db_update_timeout,
+        maxmind_db_max_size,
+        geoip_mmdb,
+        geoip_csv,
+        geoip_overrides,
+    }
+}
+
+fn build_geoip_configuration(args: &Args) -> geoip::GeoIpConfiguration {
+    let maxmind = geoip::MmdbConfiguration {
+        name: "maxmind".to_string(),
+        path: args.maxmind_db_path.clone(),
+        max_age: Some(Duration::from_secs(args.maxmind_db_max_age * 24 * 60 * 60)),
+        refuse_stale: args.maxmind_db_refuse_stale,
+        update: args
+            .maxmind_db_update_url
+            .as_ref()
+            .map(|url| geoip::DatabaseUpdate {
+                url: url.clone(),
+                sha256_url: args.maxmind_db_sha256_url.clone(),
+                cache_dir: PathBuf::from(&args.maxmind_db_cache_dir),
+                proxy: args
+                    .maxmind_db_update_via_tor
+                    .then(|| args.tor_socks5_proxy.clone()),
+                timeout: Duration::from_secs(args.maxmind_db_update_timeout),
+                max_size: args.maxmind_db_max_size,
+            }),
+    };
+    let fallbacks = args
+        .geoip_mmdb
+        .iter()
+        .map(|(name, path)| geoip::MmdbConfiguration {
+            name: name.clone(),
+            path: path.clone(),
+            max_age: None,
+            refuse_stale: false,
+            update: None,
+        });
+
+    geoip::GeoIpConfiguration {
+        overrides_path: args.geoip_overrides.clone(),
+        mmdb: std::iter::once(maxmind).chain(fallbacks).collect(),
+        csv_ranges: args
+            .geoip_csv
+            .iter()
+            .map(|(name, path)| geoip::CsvRangesConfiguration {
+                name: name.clone(),
+                path: path.clone(),
+            })
+            .collect(),
+    }
+}
+
+fn build_resolver_configuration(args: &Args) -> resolver::ResolverConfiguration {
+    let upstream = if let Some(url) = &args.dns_over_https_url {
+        resolver::Upstream::DnsOverHttps {
+            url: url.clone(),
+            proxy: args
+                .dns_over_https_via_tor
+                .then(|| args.tor_socks5_proxy.clone()),
+        }
+    } else if !args.dns_servers.is_empty() {
+        resolver::Upstream::NameServers(args.dns_servers.clone())
+    } else {
+        resolver::Upstream::System
+    };
+
+    resolver::ResolverConfiguration {
+        upstream,
+        timeout: Duration::from_secs(args.dns_timeout),
+        cache_ttl: Duration::from_secs(args.dns_cache_ttl),
+    }
+}
+
+fn write_summary(args: &Args, summary: &RunSummary) {
+    if let Some(path) = &args.summary_json {
+        let result = serde_json::to_string_pretty(summary)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!(path, error = %e, "Cannot write the summary");
+        }
+    }
+    if let Some(path) = &args.summary_markdown {
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(summary.to_markdown().as_bytes()));
+        if let Err(e) = result {
+            error!(path, error = %e, "Cannot write the summary");
+        }
     }
 }
 
 #[tokio::main]
 async fn main() {
-    init_logger();
-
     let args = parse_args();
 
+    init_logger(args.log_level.as_deref(), args.log_json);
+
     if args.dry {
         info!("Running in dry mode. No changes will be made to the database.");
     }
@@ -145,12 +864,99 @@ async fn main() {
         &args.supabase_key,
         args.dry,
     );
-    let servers_checker = adapters::servers_checker::ServersChecker::new(args.smp_server_uri);
-    let http_checker = http_checker::HttpChecker::new(args.tor_socks5_proxy.clone());
-    let geoip =
-        adapters::geoip::GeoIp::new(&args.maxmind_db_path).expect("Cannot initialize GeoIP");
+    let resolver = Arc::new(
+        resolver::Resolver::new(build_resolver_configuration(&args))
+            .expect("Cannot initialize DNS resolver"),
+    );
+    let servers_checker =
+        servers_checker::ServersChecker::new(servers_checker::ServersCheckerConfiguration {
+            smp_server_uri: args.smp_server_uri.clone(),
+            tor_socks_proxy: args.smp_client_tor_socks_proxy.clone(),
+            socks_mode: args.smp_client_socks_mode.clone(),
+            i2p_socks_proxy: args.smp_client_i2p_socks_proxy.clone(),
+        });
+    let http_checker = http_checker::HttpChecker::new(
+        http_checker::HttpCheckerConfiguration {
+            tor_socks5_proxy: args.tor_socks5_proxy.clone(),
+            isolate_tor_streams: args.tor_stream_isolation,
+            i2p_proxy: args.i2p_proxy.clone(),
+            tls_expiry_warning: Duration::from_secs(args.tls_expiry_warning_days * 24 * 60 * 60),
+            clearnet_schemes: args.info_page_schemes.clone(),
+            max_redirects: args.info_page_max_redirects,
+            timeout: Duration::from_secs(args.http_timeout),
+            user_agent: args.http_user_agent.clone(),
+            max_body_size: args.http_max_body_size,
+            allowed_content_types: args.info_page_content_types.clone(),
+        },
+        resolver.clone(),
+    )
+    .expect("Cannot initialize HTTP clients");
+    let transport_checker = transport_checker::TransportChecker::new(
+        transport_checker::TransportCheckerConfiguration {
+            tor_socks5_proxy: args.tor_socks5_proxy.clone(),
+            isolate_tor_streams: args.tor_stream_isolation,
+            timeout: Duration::from_secs(args.connect_timeout),
+        },
+        resolver.clone(),
+    )
+    .expect("Invalid Tor SOCKS5 proxy");
+    let dns_checker = dns_checker::DnsChecker::new(resolver.clone());
+    let geoip = geoip::GeoIp::new(build_geoip_configuration(&args), resolver)
+        .expect("Cannot initialize GeoIP");
 
-    let app = validator::App::new(servers_repository, servers_checker, geoip, http_checker);
+    let webhook = webhook::Webhook::new(webhook::WebhookConfiguration {
+        urls: args.webhook_urls.clone(),
+        secret: args.webhook_secret.clone().map(Secret::new),
+        timeout: Duration::from_secs(args.http_timeout),
+        is_dry: args.dry,
+    })
+    .expect("Cannot initialize webhooks");
+    let chat_messenger =
+        chat_messenger::ChatMessenger::new(chat_messenger::ChatMessengerConfiguration {
+            chat_client_uri: args.smp_server_uri.clone(),
+            is_dry: args.dry,
+        });
+    let operator_notifications = args
+        .notify_operators
+        .then_some(OperatorNotificationSettings {
+            failing_checks: args.operator_failing_checks,
+            interval: Duration::from_secs(args.operator_notification_interval * 60 * 60),
+            max_per_run: args.operator_notification_limit,
+        });
+    let metrics = Arc::new(metrics::Metrics::new());
+    let app = validator::App::new(
+        servers_repository,
+        servers_checker,
+        geoip,
+        http_checker,
+        dns_checker,
+        transport_checker,
+        metrics.clone(),
+        webhook,
+        chat_messenger,
+    )
+    .with_minimum_versions(args.min_server_version.clone(), args.min_smp_version)
+    .with_status_change_debounce(args.status_change_debounce)
+    .with_operator_notifications(operator_notifications);
 
-    app.check_servers(args.retry_count).await;
+    if !args.daemon {
+        let summary = app.check_servers(args.retry_count).await;
+        write_summary(&args, &summary);
+        return;
+    }
+
+    let listener = tokio::net::TcpListener::bind(&args.metrics_address)
+        .await
+        .expect("Cannot listen on the metrics address");
+    tokio::spawn(async move {
+        if let Err(e) = metrics::serve(metrics, listener).await {
+            error!(error = %e, "Metrics endpoint stopped");
+        }
+    });
+    loop {
+        let summary = app.check_servers(args.retry_count).await;
+        write_summary(&args, &summary);
+        info!(minutes = args.check_interval, "Next run scheduled");
+        tokio::time::sleep(Duration::from_secs(args.check_interval * 60)).await;
+    }
 }
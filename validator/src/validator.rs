@@ -1,4 +1,9 @@
 mod app;
+pub mod domain_type;
+pub mod events;
 pub mod ports;
+pub mod server_address;
+pub mod summary;
+pub mod version;
 
-pub use app::App;
+pub use app::{App, OperatorNotificationSettings};
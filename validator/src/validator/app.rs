@@ -1,97 +1,1156 @@
+use super::domain_type::{get_reserved_range, parse_origin, HostError, ReservedRange, Type};
+use super::events::{detect_events, detect_operator_notifications, get_operator_message};
 use super::ports::{
-    GeoIpPort, HttpCheckerPort, Server, ServerCheckerPort, ServerRepositoryPort, ServerStatus,
-    ServerType,
+    CheckOutcome, DnsCheckerPort, DnsStatus, GeoIpPort, HostStatus, HttpCheckerPort, HttpStatus,
+    MessageDelivery, MetricsPort, NetworkType, NotifierPort, OperatorMessengerPort, Server,
+    ServerCheckerPort, ServerRepositoryPort, ServerStatus, StoredStatus, TransportCheckerPort,
+    CONTACT_REQUEST_KIND,
 };
-use log::{error, info};
+use super::server_address::ServerAddress;
+use super::summary::RunSummary;
+use super::version::{MinimumVersions, SoftwareVersion};
 use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tracing::{error, field, info, info_span, warn, Instrument, Span};
 
-pub struct App<R: ServerRepositoryPort, SC: ServerCheckerPort, Geo: GeoIpPort, HC: HttpCheckerPort>
-{
+/// When operators that opted in get a message about their server
+#[derive(Debug, Clone, Copy)]
+pub struct OperatorNotificationSettings {
+    /// Consecutive failed checks before the operator is told their server is down
+    pub failing_checks: usize,
+    /// Minimum time between two messages of the same kind about a server
+    pub interval: Duration,
+    /// Messages sent in one run at most
+    pub max_per_run: usize,
+}
+
+pub struct App<
+    R: ServerRepositoryPort,
+    SC: ServerCheckerPort,
+    Geo: GeoIpPort,
+    HC: HttpCheckerPort,
+    DC: DnsCheckerPort,
+    TC: TransportCheckerPort,
+    M: MetricsPort,
+    N: NotifierPort,
+    OM: OperatorMessengerPort,
+> {
     server_repository: R,
     server_checker: SC,
     geoip: Geo,
     http_checker: HC,
+    dns_checker: DC,
+    transport_checker: TC,
+    metrics: M,
+    notifier: N,
+    operator_messenger: OM,
+    minimum_versions: MinimumVersions,
+    status_change_debounce: usize,
+    operator_notifications: Option<OperatorNotificationSettings>,
 }
 
-impl<R: ServerRepositoryPort, SC: ServerCheckerPort, Geo: GeoIpPort, HC: HttpCheckerPort>
-    App<R, SC, Geo, HC>
+impl<
+        R: ServerRepositoryPort,
+        SC: ServerCheckerPort,
+        Geo: GeoIpPort,
+        HC: HttpCheckerPort,
+        DC: DnsCheckerPort,
+        TC: TransportCheckerPort,
+        M: MetricsPort,
+        N: NotifierPort,
+        OM: OperatorMessengerPort,
+    > App<R, SC, Geo, HC, DC, TC, M, N, OM>
 {
-    pub fn new(server_repository: R, server_checker: SC, geoip: Geo, http_checker: HC) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        server_repository: R,
+        server_checker: SC,
+        geoip: Geo,
+        http_checker: HC,
+        dns_checker: DC,
+        transport_checker: TC,
+        metrics: M,
+        notifier: N,
+        operator_messenger: OM,
+    ) -> Self {
         Self {
             server_repository,
             server_checker,
             geoip,
             http_checker,
+            dns_checker,
+            transport_checker,
+            metrics,
+            notifier,
+            operator_messenger,
+            minimum_versions: MinimumVersions::default(),
+            status_change_debounce: 1,
+            operator_notifications: None,
         }
     }
 
-    pub async fn check_servers(&self, retry_count: u32) {
-        if let Some(mut servers) = self.server_repository.get_servers().await {
+    /// Flags servers whose software or SMP protocol version is below these minimums
+    pub fn with_minimum_versions(
+        mut self,
+        server_version: Option<SoftwareVersion>,
+        smp_version: Option<u16>,
+    ) -> Self {
+        self.minimum_versions = MinimumVersions {
+            server_version,
+            smp_version,
+        };
+        self
+    }
+
+    /// Reports a status change only once it has held for this many consecutive checks
+    pub fn with_status_change_debounce(mut self, checks: usize) -> Self {
+        self.status_change_debounce = checks.max(1);
+        self
+    }
+
+    /// Messages operators of failing servers that opted in; disabled if `None`
+    pub fn with_operator_notifications(
+        mut self,
+        settings: Option<OperatorNotificationSettings>,
+    ) -> Self {
+        self.operator_notifications = settings;
+        self
+    }
+
+    pub async fn check_servers(&self, retry_count: u32) -> RunSummary {
+        let started = Instant::now();
+        let mut summary = RunSummary::new();
+        let mut operator_messages = 0;
+        let history_count = self.status_change_debounce.max(
+            self.operator_notifications
+                .map(|settings| settings.failing_checks)
+                .unwrap_or_default(),
+        );
+        // a long-running daemon would otherwise keep the database it started with
+        let is_geoip_fresh = self.geoip.ensure_fresh().await.is_some();
+        let servers = if is_geoip_fresh {
+            self.server_repository.get_servers().await
+        } else {
+            error!("GeoIP database is stale and could not be refreshed");
+            summary.record_run_error("GeoIP database is stale".to_string());
+            None
+        };
+        let success = servers.is_some();
+        if let Some(mut servers) = servers {
+            let aliases = get_clearnet_aliases(&servers);
             servers.shuffle(&mut rand::rng());
             for server in servers {
-                if let Err(e) = self.check_server(&server, retry_count).await {
-                    error!("Error checking server {:#?}: {}", server, e);
+                let server_aliases = aliases
+                    .get(&server.identity)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                let check_started = Instant::now();
+                let span = info_span!(
+                    "server_check",
+                    server_id = %server.id,
+                    server_type = ?server.type_
+                );
+                // read before the new status is stored
+                let history = self
+                    .server_repository
+                    .get_recent_statuses(&server.id, history_count)
+                    .await
+                    .unwrap_or_default();
+                let result = self
+                    .check_server(&server, server_aliases, retry_count)
+                    .instrument(span.clone())
+                    .await;
+                let duration = check_started.elapsed();
+                self.metrics.record_check_duration(duration);
+                match result {
+                    Ok(status) => {
+                        if self
+                            .store_status(&server, &status)
+                            .instrument(span.clone())
+                            .await
+                            .is_none()
+                        {
+                            summary.record_server_error(
+                                &server,
+                                "Failed to store the server status".to_string(),
+                            );
+                        }
+                        summary.record_status(
+                            &server,
+                            &status,
+                            history.first().map(|previous| previous.outcome),
+                            duration,
+                        );
+                        self.notify_changes(&server, &status, &history)
+                            .instrument(span.clone())
+                            .await;
+                        self.notify_operator(&server, &status, &history, &mut operator_messages)
+                            .instrument(span)
+                            .await;
+                    }
+                    Err(e) => {
+                        span.in_scope(|| error!(error = %e, "Error checking server"));
+                        summary.record_error(&server, e.to_string(), duration);
+                    }
                 }
             }
-        } else {
+        } else if is_geoip_fresh {
             error!("Failed to retrieve servers from repository");
+            summary.record_run_error("Failed to retrieve servers from repository".to_string());
+        }
+        self.metrics.record_run(started.elapsed(), success);
+        summary.finish(started.elapsed());
+        log_summary(&summary);
+        summary
+    }
+
+    async fn notify_changes(
+        &self,
+        server: &Server,
+        status: &ServerStatus,
+        history: &[StoredStatus],
+    ) {
+        for event in detect_events(&server.id, status, history, self.status_change_debounce) {
+            info!(kind = ?event.kind, "Server status changed");
+            if self.notifier.notify(&event).await.is_none() {
+                warn!(kind = ?event.kind, "Failed to deliver server event");
+            }
+        }
+    }
+
+    /// Counts messages sent in this run in `sent`. A notification is only recorded once it was
+    /// delivered, so operators who haven't accepted the contact request yet get it later. Contact
+    /// requests are recorded and rate-limited like notifications.
+    async fn notify_operator(
+        &self,
+        server: &Server,
+        status: &ServerStatus,
+        history: &[StoredStatus],
+        sent: &mut usize,
+    ) {
+        let (Some(settings), Some(contact)) =
+            (self.operator_notifications, &server.operator_contact)
+        else {
+            return;
+        };
+        let notifications = detect_operator_notifications(status, history, settings.failing_checks);
+        if notifications.is_empty() {
+            return;
+        }
+        let interval = chrono::Duration::from_std(settings.interval).unwrap_or_default();
+        let is_recent = |time: chrono::DateTime<chrono::Utc>| chrono::Utc::now() - time < interval;
+        let may_request_contact = !self
+            .server_repository
+            .get_last_operator_notification(&server.id, CONTACT_REQUEST_KIND)
+            .await
+            .is_some_and(is_recent);
+        for notification in notifications {
+            if *sent >= settings.max_per_run {
+                warn!(
+                    limit = settings.max_per_run,
+                    "Not notifying the operator, message limit per run reached"
+                );
+                return;
+            }
+            let kind = notification.kind();
+            if self
+                .server_repository
+                .get_last_operator_notification(&server.id, kind)
+                .await
+                .is_some_and(is_recent)
+            {
+                continue;
+            }
+            let text = get_operator_message(server, &notification);
+            match self
+                .operator_messenger
+                .send_message(contact, &text, may_request_contact)
+                .await
+            {
+                Some(MessageDelivery::Sent) => {
+                    info!(kind, "Notified the operator");
+                    *sent += 1;
+                    self.record_operator_notification(server, kind).await;
+                }
+                Some(MessageDelivery::ContactRequested) => {
+                    info!("Asked the operator to accept a contact request");
+                    *sent += 1;
+                    self.record_operator_notification(server, CONTACT_REQUEST_KIND)
+                        .await;
+                    return;
+                }
+                Some(MessageDelivery::ContactPending) => {
+                    info!("Waiting for the operator to accept the contact request");
+                    return;
+                }
+                Some(MessageDelivery::OptedOut) => {
+                    info!("Not notifying the operator, who opted out");
+                    return;
+                }
+                None => warn!(kind, "Failed to notify the operator"),
+            }
+        }
+    }
+
+    async fn record_operator_notification(&self, server: &Server, kind: &str) {
+        if self
+            .server_repository
+            .record_operator_notification(&server.id, kind)
+            .await
+            .is_none()
+        {
+            error!(kind, "Failed to record the operator notification");
+        }
+    }
+
+    async fn store_status(&self, server: &Server, status: &ServerStatus) -> Option<()> {
+        let stored = self
+            .server_repository
+            .update_server_status(&server.id, status)
+            .await;
+        if stored.is_none() {
+            error!("Failed to store the server status");
+            self.metrics.record_repository_write_failure();
         }
+        stored
+    }
+
+    /// Status of a server refused before any check, recorded as a check of each of its hosts
+    fn reject_server(&self, server: &Server, outcome: CheckOutcome) -> ServerStatus {
+        let status = get_untested_status(&server.hosts, outcome);
+        for host in &status.hosts {
+            self.metrics.record_check(host.network, outcome);
+        }
+        status
     }
 
     async fn check_server(
         &self,
         server: &Server,
+        clearnet_aliases: &[String],
         retry_count: u32,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let server_uri = get_server_uri(server);
-        info!("Checking server status: {}", server_uri);
-        let status = {
-            let mut attempt = 0;
-            let mut result = false;
-            while attempt < retry_count && !result {
-                result = self
-                    .server_checker
-                    .check_server(&server_uri)
-                    .await
-                    .ok_or("Failed to check server")?;
-                if !result {
-                    attempt += 1;
-                    info!(
-                        "Server check failed for {}. Retrying... (attempt {}/{})",
-                        server_uri, attempt, retry_count
-                    );
-                }
+    ) -> Result<ServerStatus, Box<dyn std::error::Error>> {
+        let address = match ServerAddress::from_parts(
+            server.type_,
+            &server.identity,
+            server.password.as_ref(),
+            &server.hosts,
+        ) {
+            Ok(address) => address,
+            Err(e) => {
+                warn!(error = %e, "Server has an invalid address");
+                return Ok(self.reject_server(server, CheckOutcome::InvalidAddress));
             }
-            result
         };
-        info!("Server check result: {:?}", status);
+        let pinned_hosts = match self.pin_clearnet_hosts(&address.hosts).await {
+            Ok(pinned_hosts) => pinned_hosts,
+            Err((host, HostRejection::NonPublic(ip, range))) => {
+                warn!(host, %ip, ?range, "Refusing to check server with a non-public host");
+                return Ok(self.reject_server(server, CheckOutcome::NonPublicAddress));
+            }
+            Err((host, HostRejection::Unresolvable)) => {
+                warn!(
+                    host,
+                    "Not checking server with a host that does not resolve"
+                );
+                return Ok(self.reject_server(server, CheckOutcome::UnresolvableAddress));
+            }
+        };
+        info!(address = %address.redacted(), "Checking server status");
+        let outcome = self
+            .check_server_address(&pin_hosts(&address, &pinned_hosts), retry_count)
+            .await?;
+        info!(?outcome, "Server checked");
+        if outcome == CheckOutcome::PasswordRequired && server.password.is_some() {
+            warn!("Server rejected the configured password");
+        }
+
+        let mut hosts = vec![];
+        for host in &server.hosts {
+            let span = info_span!("host_check", host = %host, network = field::Empty);
+            hosts.push(
+                self.check_host(server, host, &pinned_hosts, outcome, retry_count)
+                    .instrument(span)
+                    .await?,
+            );
+        }
 
-        info!("Getting country information for {}...", server.host);
-        let country = self.geoip.get_country(&server.host).await;
-        info!("Done: {:?}", country);
+        let country = match hosts.iter().find_map(|host| host.country.clone()) {
+            Some(country) => Some(country),
+            None => {
+                let other_aliases: Vec<String> = clearnet_aliases
+                    .iter()
+                    .filter(|alias| !server.hosts.contains(alias))
+                    .cloned()
+                    .collect();
+                self.get_aliases_country(&other_aliases).await
+            }
+        };
 
-        info!("Checking info page availability for {}...", server.host);
-        let info_page_available = self.http_checker.is_page_available(&server.host).await;
-        info!("Done: {}", info_page_available);
+        let server_version = hosts.iter().find_map(|host| {
+            host.info_page
+                .as_ref()?
+                .page
+                .as_ref()?
+                .server_version
+                .clone()
+        });
+        let protocol_versions = hosts
+            .iter()
+            .find_map(|host| host.transport.as_ref()?.protocol_versions);
+        let outdated = self
+            .minimum_versions
+            .is_outdated(server_version.as_deref(), protocol_versions);
+        if outdated == Some(true) {
+            warn!(
+                ?server_version,
+                ?protocol_versions,
+                "Server runs an outdated version"
+            );
+        }
 
         let result = ServerStatus {
+            outcome,
             country,
-            info_page_available,
-            status,
+            info_page_available: hosts.iter().any(|host| {
+                host.info_page
+                    .as_ref()
+                    .is_some_and(|response| response.page.is_some())
+            }),
+            server_version,
+            protocol_versions,
+            outdated,
+            hosts,
         };
 
-        self.server_repository
-            .update_server_status(&server.id, &result)
+        Ok(result)
+    }
+
+    /// Catalog entries are user-submitted, so a clearnet host must not point the checks at
+    /// loopback, private or other internal addresses. Returns the vetted address and DNS records
+    /// of every clearnet name, so the later checks use them instead of resolving the name again.
+    async fn pin_clearnet_hosts(
+        &self,
+        hosts: &[String],
+    ) -> Result<HashMap<String, PinnedHost>, (String, HostRejection)> {
+        let mut pinned_hosts = HashMap::new();
+        for host in hosts {
+            let Ok(parsed) = parse_origin(host) else {
+                continue;
+            };
+            if !matches!(parsed.domain_type, Type::Clearnet) {
+                continue;
+            }
+            let dns = match parsed.value.parse::<IpAddr>() {
+                Ok(_) => None,
+                Err(_) => self.dns_checker.check_dns(host).await,
+            };
+            let addresses = match (parsed.value.parse::<IpAddr>(), &dns) {
+                (Ok(ip), _) => vec![ip],
+                (Err(_), Some(dns)) => dns.addresses.clone(),
+                (Err(_), None) => vec![],
+            };
+            if let Some((ip, range)) = addresses
+                .iter()
+                .find_map(|ip| get_reserved_range(ip).map(|range| (*ip, range)))
+            {
+                return Err((host.clone(), HostRejection::NonPublic(ip, range)));
+            }
+            // IPv4 first, as the validator may have no IPv6 connectivity
+            let Some(ip) = addresses
+                .iter()
+                .find(|ip| ip.is_ipv4())
+                .or(addresses.first())
+            else {
+                return Err((host.clone(), HostRejection::Unresolvable));
+            };
+            if let Some(dns) = dns {
+                pinned_hosts.insert(parsed.value, PinnedHost { ip: *ip, dns });
+            }
+        }
+        Ok(pinned_hosts)
+    }
+
+    async fn check_host(
+        &self,
+        server: &Server,
+        host: &str,
+        pinned_hosts: &HashMap<String, PinnedHost>,
+        combined_outcome: CheckOutcome,
+        retry_count: u32,
+    ) -> Result<HostStatus, Box<dyn std::error::Error>> {
+        let network = get_network_type(host)?;
+        Span::current().record("network", network.as_str());
+        let address = ServerAddress::from_parts(
+            server.type_,
+            &server.identity,
+            server.password.as_ref(),
+            &[host.to_string()],
+        )?;
+
+        // a single-host server was already tested by its combined address
+        let outcome = if server.hosts.len() == 1 {
+            combined_outcome
+        } else {
+            info!(address = %address.redacted(), "Checking host status");
+            let outcome = self
+                .check_server_address(&pin_hosts(&address, pinned_hosts), retry_count)
+                .await?;
+            info!(?outcome, "Host checked");
+            outcome
+        };
+
+        let location = self.geoip.get_country(host).await;
+        info!(?location, "Got country information");
+        if location.is_none() && network == NetworkType::Clearnet {
+            self.metrics.record_geoip_miss();
+        }
+
+        let HttpStatus {
+            info_page,
+            tls_certificate,
+        } = self.http_checker.check_http(host).await;
+        info!(?info_page, ?tls_certificate, "Checked info page");
+        if let Some(certificate) = &tls_certificate {
+            if !certificate.chain_valid || certificate.expires_soon {
+                warn!(
+                    chain_valid = certificate.chain_valid,
+                    not_after = %certificate.not_after,
+                    "Host certificate needs attention"
+                );
+            }
+        }
+
+        let transport = self
+            .transport_checker
+            .check_transport(host, server.type_)
             .await;
+        info!(?transport, "Checked port reachability");
+
+        let pinned = parse_origin(host)
+            .ok()
+            .and_then(|parsed| pinned_hosts.get(&parsed.value));
+        let dns = match pinned.map(|pinned| pinned.dns.clone()) {
+            Some(mut dns) => {
+                if let Some(previous) = self
+                    .server_repository
+                    .get_last_dns_addresses(&server.id, host)
+                    .await
+                {
+                    dns.changed = Some(previous != dns.addresses);
+                }
+                Some(dns)
+            }
+            None => None,
+        };
+        info!(?dns, "Checked DNS records");
+
+        let (ipv4_status, ipv6_status) = self
+            .check_address_families(&address, host, pinned_hosts, outcome, dns.as_ref())
+            .await?;
+
+        let (country, country_source) = match location {
+            Some(location) => (Some(location.country), Some(location.source)),
+            None => (None, None),
+        };
+
+        self.metrics.record_check(network, outcome);
+
+        Ok(HostStatus {
+            host: host.to_string(),
+            network,
+            country,
+            country_source,
+            info_page,
+            tls_certificate,
+            transport,
+            outcome,
+            dns,
+            ipv4_status,
+            ipv6_status,
+        })
+    }
+
+    /// Country of the first clearnet host published under the same server identity. Onion, I2P and
+    /// Yggdrasil hosts cannot be geolocated themselves, so the server is attributed to it.
+    async fn get_aliases_country(&self, clearnet_aliases: &[String]) -> Option<String> {
+        for alias in clearnet_aliases {
+            if let Some(location) = self.geoip.get_country(alias).await {
+                info!(alias, ?location, "Got country information of alias");
+                return Some(location.country);
+            }
+        }
+        None
+    }
+
+    async fn check_server_address(
+        &self,
+        address: &ServerAddress,
+        retry_count: u32,
+    ) -> Result<CheckOutcome, Box<dyn std::error::Error>> {
+        let server_uri = address.to_string();
+        let mut attempt = 0;
+        let mut result = CheckOutcome::Down;
+        // a missing password won't appear on retry, so only plain failures are retried
+        while attempt < retry_count && result == CheckOutcome::Down {
+            if attempt > 0 {
+                self.metrics.record_retry();
+            }
+            result = self
+                .server_checker
+                .check_server(&server_uri)
+                .instrument(info_span!("attempt", attempt = attempt + 1))
+                .await
+                .ok_or("Failed to check server")?;
+            if result == CheckOutcome::Down {
+                attempt += 1;
+                info!(
+                    address = %address.redacted(),
+                    attempt,
+                    retry_count,
+                    "Server check failed, retrying"
+                );
+            }
+        }
+        Ok(result)
+    }
+
+    /// Status of the server over IPv4 and IPv6. The host was tested through its pinned address,
+    /// so that family takes the host outcome; the other family is tested once, without retries,
+    /// by addressing the server with its resolved IP. `None` means the family is not applicable
+    /// to the host or its test got no answer from the server checker.
+    async fn check_address_families(
+        &self,
+        address: &ServerAddress,
+        host: &str,
+        pinned_hosts: &HashMap<String, PinnedHost>,
+        outcome: CheckOutcome,
+        dns: Option<&DnsStatus>,
+    ) -> Result<(Option<bool>, Option<bool>), Box<dyn std::error::Error>> {
+        let host = parse_origin(host)?;
+        if !matches!(host.domain_type, Type::Clearnet) {
+            return Ok((None, None));
+        }
+
+        let tested_ip = match host.value.parse::<IpAddr>() {
+            Ok(ip) => Some(ip),
+            Err(_) => pinned_hosts.get(&host.value).map(|pinned| pinned.ip),
+        };
+        let mut result = (None, None);
+        if let Some(ip) = tested_ip {
+            let status = Some(outcome == CheckOutcome::Up);
+            match ip {
+                IpAddr::V4(_) => result.0 = status,
+                IpAddr::V6(_) => result.1 = status,
+            }
+        }
+
+        let addresses = dns.map(|dns| dns.addresses.as_slice()).unwrap_or_default();
+        for ip in [
+            addresses.iter().find(|ip| ip.is_ipv4()),
+            addresses.iter().find(|ip| ip.is_ipv6()),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|ip| tested_ip.is_none_or(|tested| tested.is_ipv4() != ip.is_ipv4()))
+        {
+            let family_address = address.with_hosts(vec![ip.to_string()]);
+            info!(
+                %ip,
+                address = %family_address.redacted(),
+                "Checking server status over one address family"
+            );
+            let family_status = match self.check_server_address(&family_address, 1).await {
+                Ok(outcome) => outcome == CheckOutcome::Up,
+                Err(e) => {
+                    warn!(%ip, error = %e, "Failed to check server over one address family");
+                    continue;
+                }
+            };
+            info!(%ip, up = family_status, "Checked address family");
+            if ip.is_ipv4() {
+                result.0 = Some(family_status);
+            } else {
+                result.1 = Some(family_status);
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Vetted address of a clearnet name and the DNS records it was picked from
+struct PinnedHost {
+    ip: IpAddr,
+    dns: DnsStatus,
+}
+
+/// Why a server is not tested
+enum HostRejection {
+    NonPublic(IpAddr, ReservedRange),
+    Unresolvable,
+}
+
+/// Copy of the address with clearnet names replaced by their vetted addresses
+fn pin_hosts(address: &ServerAddress, pinned_hosts: &HashMap<String, PinnedHost>) -> ServerAddress {
+    address.with_hosts(
+        address
+            .hosts
+            .iter()
+            .map(|host| {
+                pinned_hosts
+                    .get(host)
+                    .map_or_else(|| host.clone(), |pinned| pinned.ip.to_string())
+            })
+            .collect(),
+    )
+}
 
-        Ok(())
+fn log_summary(summary: &RunSummary) {
+    let totals = &summary.totals;
+    info!(
+        duration_seconds = summary.duration_seconds,
+        checked = totals.checked,
+        up = totals.up,
+        down = totals.down,
+        unknown = totals.unknown,
+        skipped = totals.skipped,
+        "Run finished"
+    );
+    for change in &summary.changes {
+        info!(
+            server_id = %change.server_id,
+            previous = %change.previous,
+            current = %change.current,
+            "Server status changed during the run"
+        );
+    }
+    if let Some(slowest) = summary.slowest.first() {
+        info!(
+            server_id = %slowest.server_id,
+            seconds = slowest.seconds,
+            "Slowest server"
+        );
+    }
+    if !summary.errors.is_empty() {
+        warn!(errors = summary.errors.len(), "Errors during the run");
     }
 }
 
-fn get_server_uri(server: &Server) -> String {
-    let schema = match server.type_ {
-        ServerType::SMP => "smp",
-        ServerType::XFTP => "xftp",
-    };
-    format!("{}://{}@{}", schema, server.identity, server.host)
+/// Status of a server that was refused before any check; every host whose network is known
+/// carries the refusal
+fn get_untested_status(hosts: &[String], outcome: CheckOutcome) -> ServerStatus {
+    ServerStatus {
+        outcome,
+        country: None,
+        info_page_available: false,
+        server_version: None,
+        protocol_versions: None,
+        outdated: None,
+        hosts: hosts
+            .iter()
+            .filter_map(|host| {
+                Some(HostStatus {
+                    host: host.clone(),
+                    network: get_network_type(host).ok()?,
+                    country: None,
+                    country_source: None,
+                    info_page: None,
+                    tls_certificate: None,
+                    transport: None,
+                    outcome,
+                    dns: None,
+                    ipv4_status: None,
+                    ipv6_status: None,
+                })
+            })
+            .collect(),
+    }
+}
+
+fn get_network_type(host: &str) -> Result<NetworkType, HostError> {
+    Ok(match parse_origin(host)?.domain_type {
+        Type::Clearnet => NetworkType::Clearnet,
+        Type::Onion => NetworkType::Tor,
+        Type::I2p => NetworkType::I2p,
+        Type::Yggdrasil => NetworkType::Yggdrasil,
+    })
+}
+
+/// Clearnet hosts of every server identity, used to geolocate its overlay network hosts
+fn get_clearnet_aliases(servers: &[Server]) -> HashMap<String, Vec<String>> {
+    let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+    for server in servers {
+        for host in &server.hosts {
+            if get_network_type(host) == Ok(NetworkType::Clearnet) {
+                aliases
+                    .entry(server.identity.clone())
+                    .or_default()
+                    .push(host.clone());
+            }
+        }
+    }
+    aliases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator::ports::{GeoLocation, ServerType, TransportStatus};
+    use chrono::{DateTime, Utc};
+    use std::sync::Mutex;
+
+    /// Stands in for the ports that notifying operators doesn't use
+    struct Unused;
+
+    /// Answers every test with `outcome` and keeps the tested URLs
+    struct FakeServerChecker {
+        outcome: CheckOutcome,
+        urls: Mutex<Vec<String>>,
+    }
+
+    impl ServerCheckerPort for FakeServerChecker {
+        async fn check_server(&self, url: &str) -> Option<CheckOutcome> {
+            self.urls.lock().expect("Lock").push(url.to_string());
+            Some(self.outcome)
+        }
+    }
+
+    impl GeoIpPort for Unused {
+        async fn ensure_fresh(&self) -> Option<()> {
+            None
+        }
+
+        async fn get_country(&self, _host: &str) -> Option<GeoLocation> {
+            None
+        }
+    }
+
+    impl HttpCheckerPort for Unused {
+        async fn check_http(&self, _host: &str) -> HttpStatus {
+            HttpStatus::default()
+        }
+    }
+
+    impl DnsCheckerPort for Unused {
+        async fn check_dns(&self, _host: &str) -> Option<DnsStatus> {
+            None
+        }
+    }
+
+    impl TransportCheckerPort for Unused {
+        async fn check_transport(&self, _host: &str, _type: ServerType) -> Option<TransportStatus> {
+            None
+        }
+    }
+
+    /// Keeps the recorded checks
+    #[derive(Default)]
+    struct FakeMetrics {
+        checks: Mutex<Vec<(NetworkType, CheckOutcome)>>,
+    }
+
+    impl MetricsPort for FakeMetrics {
+        fn record_check(&self, network: NetworkType, outcome: CheckOutcome) {
+            self.checks.lock().expect("Lock").push((network, outcome));
+        }
+        fn record_check_duration(&self, _duration: Duration) {}
+        fn record_retry(&self) {}
+        fn record_repository_write_failure(&self) {}
+        fn record_geoip_miss(&self) {}
+        fn record_run(&self, _duration: Duration, _success: bool) {}
+    }
+
+    impl NotifierPort for Unused {
+        async fn notify(&self, _event: &crate::validator::ports::StatusEvent) -> Option<()> {
+            Some(())
+        }
+    }
+
+    /// Keeps the notifications recorded by kind
+    #[derive(Default)]
+    struct FakeRepository {
+        notifications: Mutex<Vec<(String, DateTime<Utc>)>>,
+    }
+
+    impl ServerRepositoryPort for FakeRepository {
+        async fn get_servers(&self) -> Option<Vec<Server>> {
+            None
+        }
+
+        async fn get_recent_statuses(
+            &self,
+            _server_id: &str,
+            _count: usize,
+        ) -> Option<Vec<StoredStatus>> {
+            None
+        }
+
+        async fn get_last_operator_notification(
+            &self,
+            _server_id: &str,
+            kind: &str,
+        ) -> Option<DateTime<Utc>> {
+            self.notifications
+                .lock()
+                .expect("Lock")
+                .iter()
+                .filter(|(recorded, _)| recorded == kind)
+                .map(|(_, time)| *time)
+                .max()
+        }
+
+        async fn record_operator_notification(&self, _server_id: &str, kind: &str) -> Option<()> {
+            self.notifications
+                .lock()
+                .expect("Lock")
+                .push((kind.to_string(), Utc::now()));
+            Some(())
+        }
+
+        async fn get_last_dns_addresses(
+            &self,
+            _server_id: &str,
+            _host: &str,
+        ) -> Option<Vec<IpAddr>> {
+            None
+        }
+
+        async fn update_server_status(
+            &self,
+            _server_id: &str,
+            _status: &ServerStatus,
+        ) -> Option<()> {
+            Some(())
+        }
+    }
+
+    /// Answers every message with `delivery` and keeps whether a contact request was allowed
+    struct FakeMessenger {
+        delivery: Option<MessageDelivery>,
+        calls: Mutex<Vec<bool>>,
+    }
+
+    impl OperatorMessengerPort for FakeMessenger {
+        async fn send_message(
+            &self,
+            _contact_address: &str,
+            _text: &str,
+            may_request_contact: bool,
+        ) -> Option<MessageDelivery> {
+            self.calls.lock().expect("Lock").push(may_request_contact);
+            self.delivery
+        }
+    }
+
+    type TestApp = App<
+        FakeRepository,
+        FakeServerChecker,
+        Unused,
+        Unused,
+        Unused,
+        Unused,
+        FakeMetrics,
+        Unused,
+        FakeMessenger,
+    >;
+
+    fn get_app(delivery: Option<MessageDelivery>, recorded: &[&str]) -> TestApp {
+        let repository = FakeRepository::default();
+        repository.notifications.lock().expect("Lock").extend(
+            recorded
+                .iter()
+                .map(|kind| (kind.to_string(), Utc::now() - chrono::Duration::hours(1))),
+        );
+        App::new(
+            repository,
+            FakeServerChecker {
+                outcome: CheckOutcome::Down,
+                urls: Mutex::new(vec![]),
+            },
+            Unused,
+            Unused,
+            Unused,
+            Unused,
+            FakeMetrics::default(),
+            Unused,
+            FakeMessenger {
+                delivery,
+                calls: Mutex::new(vec![]),
+            },
+        )
+        .with_operator_notifications(Some(OperatorNotificationSettings {
+            failing_checks: 1,
+            interval: Duration::from_secs(24 * 60 * 60),
+            max_per_run: 10,
+        }))
+    }
+
+    fn server() -> Server {
+        Server {
+            type_: ServerType::SMP,
+            id: "server".to_string(),
+            identity: String::new(),
+            password: None,
+            hosts: vec!["smp.example.com".to_string()],
+            operator_contact: Some("simplex:/contact#/?v=2".to_string()),
+        }
+    }
+
+    fn down() -> ServerStatus {
+        get_untested_status(&[], CheckOutcome::Down)
+    }
+
+    fn recorded_kinds(app: &TestApp) -> Vec<String> {
+        app.server_repository
+            .notifications
+            .lock()
+            .expect("Lock")
+            .iter()
+            .map(|(kind, _)| kind.clone())
+            .collect()
+    }
+
+    fn messenger_calls(app: &TestApp) -> Vec<bool> {
+        app.operator_messenger.calls.lock().expect("Lock").clone()
+    }
+
+    #[tokio::test]
+    async fn test_notifies_operator() {
+        let app = get_app(Some(MessageDelivery::Sent), &[]);
+        let mut sent = 0;
+        app.notify_operator(&server(), &down(), &[], &mut sent)
+            .await;
+        assert_eq!(sent, 1);
+        assert_eq!(recorded_kinds(&app), ["failing"]);
+        assert_eq!(messenger_calls(&app), [true]);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_notifications() {
+        let app = get_app(Some(MessageDelivery::Sent), &["failing"]);
+        let mut sent = 0;
+        app.notify_operator(&server(), &down(), &[], &mut sent)
+            .await;
+        assert_eq!(sent, 0);
+        assert!(messenger_calls(&app).is_empty());
+
+        let app = get_app(Some(MessageDelivery::Sent), &[]);
+        let mut sent = 10;
+        app.notify_operator(&server(), &down(), &[], &mut sent)
+            .await;
+        assert!(messenger_calls(&app).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_records_contact_request() {
+        let app = get_app(Some(MessageDelivery::ContactRequested), &[]);
+        let mut sent = 0;
+        app.notify_operator(&server(), &down(), &[], &mut sent)
+            .await;
+        assert_eq!(sent, 1);
+        assert_eq!(recorded_kinds(&app), [CONTACT_REQUEST_KIND]);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_contact_requests() {
+        let app = get_app(
+            Some(MessageDelivery::ContactPending),
+            &[CONTACT_REQUEST_KIND],
+        );
+        let mut sent = 0;
+        app.notify_operator(&server(), &down(), &[], &mut sent)
+            .await;
+        assert_eq!(sent, 0);
+        assert_eq!(messenger_calls(&app), [false]);
+        assert_eq!(recorded_kinds(&app), [CONTACT_REQUEST_KIND]);
+    }
+
+    #[tokio::test]
+    async fn test_respects_opt_out() {
+        let app = get_app(Some(MessageDelivery::OptedOut), &[]);
+        let mut sent = 0;
+        app.notify_operator(&server(), &down(), &[], &mut sent)
+            .await;
+        assert_eq!(sent, 0);
+        assert!(recorded_kinds(&app).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_not_recorded() {
+        let app = get_app(None, &[]);
+        let mut sent = 0;
+        app.notify_operator(&server(), &down(), &[], &mut sent)
+            .await;
+        assert_eq!(sent, 0);
+        assert!(recorded_kinds(&app).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_address_families_are_tested_once() {
+        let app = get_app(None, &[]);
+        let address = ServerAddress::from_parts(
+            ServerType::SMP,
+            "u2dS9sG8nMNURyZwqASV4yROM28Er0luVTx5X1CsMrU=",
+            None,
+            &["smp.example.com".to_string()],
+        )
+        .expect("Valid address");
+        let v4: IpAddr = "93.184.215.14".parse().expect("Valid address");
+        let v6: IpAddr = "2606:2800:21f:cb07:6820:80da:af6b:8b2c"
+            .parse()
+            .expect("Valid address");
+        let dns = DnsStatus {
+            resolves: true,
+            has_a: true,
+            has_aaaa: true,
+            cname_chain: vec![],
+            ttl: None,
+            addresses: vec![v4, v6],
+            changed: None,
+        };
+        let pinned_hosts = HashMap::from([(
+            "smp.example.com".to_string(),
+            PinnedHost {
+                ip: v4,
+                dns: dns.clone(),
+            },
+        )]);
+        let families = app
+            .check_address_families(
+                &address,
+                "smp.example.com",
+                &pinned_hosts,
+                CheckOutcome::Up,
+                Some(&dns),
+            )
+            .await
+            .expect("Checked");
+        // IPv4 was tested through the pinned address; IPv6 gets a single attempt
+        assert_eq!(families, (Some(true), Some(false)));
+        let urls = app.server_checker.urls.lock().expect("Lock").clone();
+        assert_eq!(urls.len(), 1);
+        assert!(urls[0].contains(&v6.to_string()));
+    }
+    #[tokio::test]
+    async fn test_rejected_server_has_host_statuses() {
+        let app = get_app(None, &[]);
+        let server = Server {
+            identity: "u2dS9sG8nMNURyZwqASV4yROM28Er0luVTx5X1CsMrU=".to_string(),
+            hosts: vec!["127.0.0.1".to_string()],
+            ..server()
+        };
+        let status = app.check_server(&server, &[], 1).await.expect("Checked");
+
+        assert_eq!(status.outcome, CheckOutcome::NonPublicAddress);
+        assert_eq!(status.hosts.len(), 1);
+        assert_eq!(status.hosts[0].network, NetworkType::Clearnet);
+        assert_eq!(status.hosts[0].outcome, CheckOutcome::NonPublicAddress);
+        assert_eq!(
+            *app.metrics.checks.lock().expect("Lock"),
+            vec![(NetworkType::Clearnet, CheckOutcome::NonPublicAddress)]
+        );
+        assert!(app.server_checker.urls.lock().expect("Lock").is_empty());
+    }
 }
@@ -0,0 +1,495 @@
+use data_encoding::BASE32_NOPAD;
+use sha3::{Digest, Sha3_256};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Base32 length of an onion v3 address: 32-byte public key, 2-byte checksum and version
+const ONION_V3_LENGTH: usize = 56;
+const ONION_V3_VERSION: u8 = 3;
+/// Base32 length of an I2P destination hash; longer `.b32.i2p` names are encrypted leasesets
+const I2P_B32_LENGTH: usize = 52;
+const I2P_B33_MIN_LENGTH: usize = 56;
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_DOMAIN_LENGTH: usize = 253;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Clearnet,
+    Onion,
+    I2p,
+    Yggdrasil,
+}
+
+pub struct Host {
+    pub domain_type: Type,
+    pub value: String,
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HostError {
+    Empty,
+    UnbalancedBrackets(String),
+    InvalidIpv6(String),
+    InvalidPort(String),
+    InvalidDomain(String),
+    InvalidOnion(String),
+    InvalidI2p(String),
+}
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostError::Empty => write!(f, "empty host"),
+            HostError::UnbalancedBrackets(value) => {
+                write!(f, "unbalanced brackets in \"{}\"", value)
+            }
+            HostError::InvalidIpv6(value) => write!(f, "invalid IPv6 address \"{}\"", value),
+            HostError::InvalidPort(port) => write!(f, "invalid port \"{}\"", port),
+            HostError::InvalidDomain(value) => write!(f, "invalid domain \"{}\"", value),
+            HostError::InvalidOnion(value) => write!(f, "invalid onion v3 address \"{}\"", value),
+            HostError::InvalidI2p(value) => write!(f, "invalid I2P address \"{}\"", value),
+        }
+    }
+}
+
+impl std::error::Error for HostError {}
+
+/// Address ranges that are not reachable on the public internet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReservedRange {
+    Unspecified,
+    Loopback,
+    /// RFC 1918 and IPv6 unique local addresses
+    Private,
+    /// Carrier-grade NAT, RFC 6598
+    SharedAddressSpace,
+    LinkLocal,
+    Multicast,
+    Documentation,
+    /// NAT64 and 6to4 prefixes, which embed an IPv4 address that may be internal
+    Translation,
+    /// Protocol assignments, broadcast, benchmarking and future-use ranges
+    Reserved,
+}
+
+fn get_reserved_v4_range(ip: &Ipv4Addr) -> Option<ReservedRange> {
+    let [a, b, c, _] = ip.octets();
+    match (a, b, c) {
+        (0, _, _) => Some(ReservedRange::Unspecified),
+        (127, _, _) => Some(ReservedRange::Loopback),
+        (10, _, _) | (172, 16..=31, _) | (192, 168, _) => Some(ReservedRange::Private),
+        (100, 64..=127, _) => Some(ReservedRange::SharedAddressSpace),
+        (169, 254, _) => Some(ReservedRange::LinkLocal),
+        (224..=239, _, _) => Some(ReservedRange::Multicast),
+        (192, 0, 2) | (198, 51, 100) | (203, 0, 113) => Some(ReservedRange::Documentation),
+        (192, 0, 0) | (198, 18..=19, _) | (240..=255, _, _) => Some(ReservedRange::Reserved),
+        _ => None,
+    }
+}
+
+fn get_reserved_v6_range(ip: &Ipv6Addr) -> Option<ReservedRange> {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return get_reserved_v4_range(&v4);
+    }
+    let segments = ip.segments();
+    match segments[0] {
+        _ if ip.is_unspecified() => Some(ReservedRange::Unspecified),
+        _ if ip.is_loopback() => Some(ReservedRange::Loopback),
+        s if s & 0xfe00 == 0xfc00 => Some(ReservedRange::Private),
+        s if s & 0xffc0 == 0xfe80 => Some(ReservedRange::LinkLocal),
+        s if s & 0xff00 == 0xff00 => Some(ReservedRange::Multicast),
+        0x2001 if segments[1] == 0x0db8 => Some(ReservedRange::Documentation),
+        // 3fff::/20, RFC 9637
+        0x3fff if segments[1] & 0xf000 == 0 => Some(ReservedRange::Documentation),
+        // 64:ff9b::/96 and the local-use 64:ff9b:1::/48, RFC 8215
+        0x0064 if segments[1..6] == [0xff9b, 0, 0, 0, 0] || segments[1..3] == [0xff9b, 1] => {
+            Some(ReservedRange::Translation)
+        }
+        0x2002 => Some(ReservedRange::Translation),
+        _ => None,
+    }
+}
+
+/// Returns the range of a non-public address; Yggdrasil addresses count as public
+pub fn get_reserved_range(ip: &IpAddr) -> Option<ReservedRange> {
+    match ip {
+        IpAddr::V4(v4) => get_reserved_v4_range(v4),
+        IpAddr::V6(v6) => get_reserved_v6_range(v6),
+    }
+}
+
+fn is_yggdrasil(ip: &IpAddr) -> bool {
+    if let IpAddr::V6(v6) = ip {
+        let s = v6.segments()[0];
+        s & 0xfe00 == 0x0200
+    } else {
+        false
+    }
+}
+
+fn parse_port(port: &str) -> Result<u16, HostError> {
+    match port.parse::<u16>() {
+        Ok(value) if value != 0 => Ok(value),
+        _ => Err(HostError::InvalidPort(port.to_string())),
+    }
+}
+
+/// Splits `host`, `host:port`, `[ipv6]`, `[ipv6]:port` or a bare IPv6 address
+fn split_authority(authority: &str) -> Result<(&str, Option<u16>), HostError> {
+    if authority.is_empty() {
+        return Err(HostError::Empty);
+    }
+
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (ip, after) = rest
+            .split_once(']')
+            .ok_or_else(|| HostError::UnbalancedBrackets(authority.to_string()))?;
+        if ip.parse::<Ipv6Addr>().is_err() {
+            return Err(HostError::InvalidIpv6(ip.to_string()));
+        }
+        let port = match after {
+            "" => None,
+            _ => Some(parse_port(after.strip_prefix(':').ok_or_else(|| {
+                HostError::UnbalancedBrackets(authority.to_string())
+            })?)?),
+        };
+        return Ok((ip, port));
+    }
+    if authority.contains(']') {
+        return Err(HostError::UnbalancedBrackets(authority.to_string()));
+    }
+
+    if authority.bytes().filter(|&b| b == b':').count() > 1 {
+        // bare IPv6 cannot carry a port
+        if authority.parse::<Ipv6Addr>().is_err() {
+            return Err(HostError::InvalidIpv6(authority.to_string()));
+        }
+        return Ok((authority, None));
+    }
+
+    match authority.split_once(':') {
+        Some((host, port)) => Ok((host, Some(parse_port(port)?))),
+        None => Ok((authority, None)),
+    }
+}
+
+/// Converts internationalized names to punycode and checks the result is a valid LDH hostname
+fn normalize_domain(host: &str) -> Result<String, HostError> {
+    let invalid = || HostError::InvalidDomain(host.to_string());
+
+    let domain = idna::domain_to_ascii(host).map_err(|_| invalid())?;
+    let labels: Vec<&str> = domain.split('.').collect();
+    let is_valid_label = |label: &&str| {
+        !label.is_empty()
+            && label.len() <= MAX_LABEL_LENGTH
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    // an all-numeric top-level label is a mistyped IPv4 address, not a domain
+    let is_numeric = labels
+        .last()
+        .is_some_and(|label| label.chars().all(|c| c.is_ascii_digit()));
+    if domain.len() > MAX_DOMAIN_LENGTH || !labels.iter().all(is_valid_label) || is_numeric {
+        return Err(invalid());
+    }
+    Ok(domain)
+}
+
+/// Label right before the given suffix, e.g. the key part of `www.<key>.onion`
+fn label_before<'a>(domain: &'a str, suffix: &str) -> Option<&'a str> {
+    domain.strip_suffix(suffix)?.rsplit('.').next()
+}
+
+/// Checks the version byte and the SHA3-256 checksum embedded in an onion v3 address
+fn is_onion_v3(label: &str) -> bool {
+    if label.len() != ONION_V3_LENGTH {
+        return false;
+    }
+    let Ok(decoded) = BASE32_NOPAD.decode(label.to_ascii_uppercase().as_bytes()) else {
+        return false;
+    };
+    let (public_key, rest) = decoded.split_at(32);
+    let (checksum, version) = rest.split_at(2);
+    if version != [ONION_V3_VERSION] {
+        return false;
+    }
+    let expected = Sha3_256::new()
+        .chain_update(b".onion checksum")
+        .chain_update(public_key)
+        .chain_update(version)
+        .finalize();
+    expected[..2] == *checksum
+}
+
+fn is_i2p_b32(label: &str) -> bool {
+    (label.len() == I2P_B32_LENGTH || label.len() >= I2P_B33_MIN_LENGTH)
+        && BASE32_NOPAD
+            .decode(label.to_ascii_uppercase().as_bytes())
+            .is_ok()
+}
+
+pub fn parse_origin(authority: &str) -> Result<Host, HostError> {
+    let (host, port) = split_authority(authority)?;
+
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        let domain_type = if is_yggdrasil(&ip) {
+            Type::Yggdrasil
+        } else {
+            Type::Clearnet
+        };
+        return Ok(Host {
+            domain_type,
+            value: ip.to_string(),
+            port,
+        });
+    }
+
+    let value = normalize_domain(host)?;
+    let domain_type = if let Some(key) = label_before(&value, ".onion") {
+        if !is_onion_v3(key) {
+            return Err(HostError::InvalidOnion(host.to_string()));
+        }
+        Type::Onion
+    } else if let Some(hash) = label_before(&value, ".b32.i2p") {
+        if !is_i2p_b32(hash) {
+            return Err(HostError::InvalidI2p(host.to_string()));
+        }
+        Type::I2p
+    } else if value.ends_with(".i2p") {
+        // human-readable names resolved by the router's address book
+        Type::I2p
+    } else {
+        Type::Clearnet
+    };
+
+    Ok(Host {
+        domain_type,
+        value,
+        port,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONION: &str = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+
+    #[test]
+    fn test_domain_no_port() {
+        let h = parse_origin("example.com").expect("valid host");
+        assert_eq!(h.value, "example.com");
+        assert_eq!(h.port, None);
+    }
+
+    #[test]
+    fn test_domain_with_port() {
+        let h = parse_origin("example.com:8443").expect("valid host");
+        assert_eq!(h.value, "example.com");
+        assert_eq!(h.port, Some(8443));
+    }
+
+    #[test]
+    fn test_ipv4_no_port() {
+        let h = parse_origin("1.2.3.4").expect("valid host");
+        assert_eq!(h.value, "1.2.3.4");
+        assert_eq!(h.port, None);
+    }
+
+    #[test]
+    fn test_ipv4_with_port() {
+        let h = parse_origin("1.2.3.4:8443").expect("valid host");
+        assert_eq!(h.value, "1.2.3.4");
+        assert_eq!(h.port, Some(8443));
+    }
+
+    #[test]
+    fn test_ipv6_bare() {
+        let h = parse_origin("2001:db8::1").expect("valid host");
+        assert_eq!(h.value, "2001:db8::1");
+        assert_eq!(h.port, None);
+    }
+
+    #[test]
+    fn test_ipv6_bracketed_no_port() {
+        let h = parse_origin("[2001:db8::1]").expect("valid host");
+        assert_eq!(h.value, "2001:db8::1");
+        assert_eq!(h.port, None);
+    }
+
+    #[test]
+    fn test_ipv6_bracketed_with_port() {
+        let h = parse_origin("[2001:db8::1]:8443").expect("valid host");
+        assert_eq!(h.value, "2001:db8::1");
+        assert_eq!(h.port, Some(8443));
+    }
+
+    #[test]
+    fn test_onion_no_port() {
+        let h = parse_origin(ONION).expect("valid host");
+        assert!(matches!(h.domain_type, Type::Onion));
+        assert_eq!(h.value, ONION);
+        assert_eq!(h.port, None);
+    }
+
+    #[test]
+    fn test_onion_with_port() {
+        let h = parse_origin(&format!("{}:9000", ONION.to_uppercase())).expect("valid host");
+        assert_eq!(h.value, ONION);
+        assert_eq!(h.port, Some(9000));
+    }
+
+    #[test]
+    fn test_onion_subdomain() {
+        let h = parse_origin(&format!("www.{}", ONION)).expect("valid host");
+        assert!(matches!(h.domain_type, Type::Onion));
+    }
+
+    #[test]
+    fn test_onion_invalid() {
+        // v2 length
+        assert!(matches!(
+            parse_origin("expyuzz4wqqyqhjn.onion"),
+            Err(HostError::InvalidOnion(_))
+        ));
+        // one character changed breaks the checksum
+        assert!(matches!(
+            parse_origin(&ONION.replacen('d', "e", 1)),
+            Err(HostError::InvalidOnion(_))
+        ));
+        // wrong version byte
+        assert!(matches!(
+            parse_origin(&ONION.replace("czad.onion", "czae.onion")),
+            Err(HostError::InvalidOnion(_))
+        ));
+    }
+
+    #[test]
+    fn test_i2p_b32_no_port() {
+        let h = parse_origin("ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p")
+            .expect("valid host");
+        assert!(matches!(h.domain_type, Type::I2p));
+        assert_eq!(
+            h.value,
+            "ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p"
+        );
+        assert_eq!(h.port, None);
+    }
+
+    #[test]
+    fn test_i2p_name_with_port() {
+        let h = parse_origin("abc.i2p:5223").expect("valid host");
+        assert!(matches!(h.domain_type, Type::I2p));
+        assert_eq!(h.value, "abc.i2p");
+        assert_eq!(h.port, Some(5223));
+    }
+
+    #[test]
+    fn test_i2p_b32_invalid() {
+        assert!(matches!(
+            parse_origin("abc.b32.i2p"),
+            Err(HostError::InvalidI2p(_))
+        ));
+    }
+
+    #[test]
+    fn test_idn_normalized_to_punycode() {
+        let h = parse_origin("Bücher.example:443").expect("valid host");
+        assert_eq!(h.value, "xn--bcher-kva.example");
+        assert_eq!(h.port, Some(443));
+    }
+
+    #[test]
+    fn test_invalid_authorities() {
+        assert_eq!(parse_origin("").err(), Some(HostError::Empty));
+        assert!(matches!(
+            parse_origin("foo:bar:baz"),
+            Err(HostError::InvalidIpv6(_))
+        ));
+        assert!(matches!(
+            parse_origin("example.com:65536"),
+            Err(HostError::InvalidPort(_))
+        ));
+        assert!(matches!(
+            parse_origin("example.com:0"),
+            Err(HostError::InvalidPort(_))
+        ));
+        assert!(matches!(
+            parse_origin("[2001:db8::1"),
+            Err(HostError::UnbalancedBrackets(_))
+        ));
+        assert!(matches!(
+            parse_origin("2001:db8::1]"),
+            Err(HostError::UnbalancedBrackets(_))
+        ));
+        assert!(matches!(
+            parse_origin("[2001:db8::1]8443"),
+            Err(HostError::UnbalancedBrackets(_))
+        ));
+        assert!(matches!(
+            parse_origin("exa mple.com"),
+            Err(HostError::InvalidDomain(_))
+        ));
+        assert!(matches!(
+            parse_origin("-example.com"),
+            Err(HostError::InvalidDomain(_))
+        ));
+        assert!(matches!(
+            parse_origin("256.1.1.1"),
+            Err(HostError::InvalidDomain(_))
+        ));
+    }
+
+    #[test]
+    fn test_reserved_ranges() {
+        let range = |value: &str| get_reserved_range(&value.parse().expect("valid address"));
+        assert_eq!(range("0.0.0.0"), Some(ReservedRange::Unspecified));
+        assert_eq!(range("127.0.0.1"), Some(ReservedRange::Loopback));
+        assert_eq!(range("10.0.0.5"), Some(ReservedRange::Private));
+        assert_eq!(range("172.31.255.255"), Some(ReservedRange::Private));
+        assert_eq!(range("192.168.1.1"), Some(ReservedRange::Private));
+        assert_eq!(
+            range("100.100.0.1"),
+            Some(ReservedRange::SharedAddressSpace)
+        );
+        assert_eq!(range("169.254.169.254"), Some(ReservedRange::LinkLocal));
+        assert_eq!(range("239.255.255.250"), Some(ReservedRange::Multicast));
+        assert_eq!(range("203.0.113.7"), Some(ReservedRange::Documentation));
+        assert_eq!(range("255.255.255.255"), Some(ReservedRange::Reserved));
+        assert_eq!(range("::"), Some(ReservedRange::Unspecified));
+        assert_eq!(range("::1"), Some(ReservedRange::Loopback));
+        assert_eq!(range("::ffff:10.1.2.3"), Some(ReservedRange::Private));
+        assert_eq!(range("fd00::1"), Some(ReservedRange::Private));
+        assert_eq!(range("fe80::1"), Some(ReservedRange::LinkLocal));
+        assert_eq!(range("ff02::1"), Some(ReservedRange::Multicast));
+        assert_eq!(range("2001:db8::1"), Some(ReservedRange::Documentation));
+        assert_eq!(range("3fff::1"), Some(ReservedRange::Documentation));
+        assert_eq!(range("3fff:fff::1"), Some(ReservedRange::Documentation));
+        assert_eq!(range("192.0.0.8"), Some(ReservedRange::Reserved));
+        assert_eq!(range("64:ff9b::7f00:1"), Some(ReservedRange::Translation));
+        assert_eq!(range("64:ff9b:1::1"), Some(ReservedRange::Translation));
+        assert_eq!(range("2002:7f00:1::1"), Some(ReservedRange::Translation));
+    }
+
+    #[test]
+    fn test_public_addresses() {
+        let range = |value: &str| get_reserved_range(&value.parse().expect("valid address"));
+        assert_eq!(range("1.1.1.1"), None);
+        assert_eq!(range("172.32.0.1"), None);
+        assert_eq!(range("100.128.0.1"), None);
+        assert_eq!(range("2606:4700:4700::1111"), None);
+        // global unicast next to the documentation prefix
+        assert_eq!(range("3ff0::1"), None);
+        assert_eq!(range("3fff:1000::1"), None);
+        assert_eq!(range("192.0.1.1"), None);
+        assert_eq!(range("21e:a51c:885b:7db0:166e:927:98cd:d186"), None);
+    }
+
+    #[test]
+    fn test_i2p_suffix_only() {
+        let h = parse_origin("i2p.example.com").expect("valid host");
+        assert!(matches!(h.domain_type, Type::Clearnet));
+    }
+}
@@ -0,0 +1,284 @@
+use super::ports::{
+    CheckOutcome, OperatorNotification, Server, ServerStatus, StatusEvent, StatusEventKind,
+    StoredStatus,
+};
+
+/// Previous value if `current` replaced it and was seen in every one of the last `debounce`
+/// checks, counting the current one. History is ordered from the most recent check and unknown
+/// values never count as a change.
+fn get_settled_change<'a, T: PartialEq>(
+    current: &T,
+    history: &'a [Option<T>],
+    debounce: usize,
+) -> Option<&'a T> {
+    let debounce = debounce.max(1);
+    let previous = history.get(debounce - 1)?.as_ref()?;
+    let confirmed = history[..debounce - 1]
+        .iter()
+        .all(|value| value.as_ref() == Some(current));
+    (confirmed && previous != current).then_some(previous)
+}
+
+/// Password-protected and untested servers are neither up nor down
+fn is_up(outcome: CheckOutcome) -> Option<bool> {
+    match outcome {
+        CheckOutcome::Up => Some(true),
+        CheckOutcome::Down => Some(false),
+        _ => None,
+    }
+}
+
+/// Events for a new status compared with the stored history of the server, most recent first.
+/// With a `debounce` of N a change is only reported once it has held for N checks, so a
+/// server that is down for a single check reports nothing.
+pub fn detect_events(
+    server_id: &str,
+    status: &ServerStatus,
+    history: &[StoredStatus],
+    debounce: usize,
+) -> Vec<StatusEvent> {
+    let mut events = vec![];
+
+    if let Some(up) = is_up(status.outcome) {
+        let history: Vec<Option<bool>> =
+            history.iter().map(|stored| is_up(stored.outcome)).collect();
+        if get_settled_change(&up, &history, debounce).is_some() {
+            events.push(StatusEvent {
+                server_id: server_id.to_string(),
+                kind: if up {
+                    StatusEventKind::WentUp
+                } else {
+                    StatusEventKind::WentDown
+                },
+            });
+        }
+    }
+
+    if let Some(country) = &status.country {
+        let history: Vec<Option<String>> = history
+            .iter()
+            .map(|stored| stored.country.clone())
+            .collect();
+        if let Some(previous) = get_settled_change(country, &history, debounce) {
+            events.push(StatusEvent {
+                server_id: server_id.to_string(),
+                kind: StatusEventKind::CountryChanged {
+                    previous: previous.clone(),
+                    current: country.clone(),
+                },
+            });
+        }
+    }
+
+    events
+}
+
+/// Whether the most recent `count` checks of `history` were all down
+fn is_down_in_last(history: &[StoredStatus], count: usize) -> bool {
+    history.len() >= count
+        && history[..count]
+            .iter()
+            .all(|stored| stored.outcome == CheckOutcome::Down)
+}
+
+/// What the operator should hear about: the server starting to fail, that is being down in the
+/// last `failing_checks` checks including this one but not in the ones before, the server being
+/// up again after it failed, and certificates about to expire
+pub fn detect_operator_notifications(
+    status: &ServerStatus,
+    history: &[StoredStatus],
+    failing_checks: usize,
+) -> Vec<OperatorNotification> {
+    let mut notifications = vec![];
+
+    let failing_checks = failing_checks.max(1);
+    let was_failing = is_down_in_last(history, failing_checks);
+    match status.outcome {
+        CheckOutcome::Down if !was_failing && is_down_in_last(history, failing_checks - 1) => {
+            notifications.push(OperatorNotification::Failing {
+                checks: failing_checks,
+            });
+        }
+        CheckOutcome::Up if was_failing => notifications.push(OperatorNotification::Recovered),
+        _ => {}
+    }
+
+    for host in &status.hosts {
+        if let Some(certificate) = host
+            .tls_certificate
+            .as_ref()
+            .filter(|certificate| certificate.expires_soon)
+        {
+            notifications.push(OperatorNotification::CertificateExpiring {
+                host: host.host.clone(),
+                not_after: certificate.not_after,
+            });
+        }
+    }
+
+    notifications
+}
+
+/// Replies of the operator that stop and resume the notifications
+pub const OPT_OUT_COMMAND: &str = "/stop";
+pub const OPT_IN_COMMAND: &str = "/start";
+
+pub fn get_operator_message(server: &Server, notification: &OperatorNotification) -> String {
+    let problem = match notification {
+        OperatorNotification::Failing { checks } => format!(
+            "Your server {} failed the last {} checks of the SimpleX server catalog.",
+            server.hosts.join(", "),
+            checks
+        ),
+        OperatorNotification::Recovered => format!(
+            "Your server {} passes the checks of the SimpleX server catalog again.",
+            server.hosts.join(", ")
+        ),
+        OperatorNotification::CertificateExpiring { host, not_after } => format!(
+            "The TLS certificate of {} expires on {}.",
+            host,
+            not_after.format("%Y-%m-%d")
+        ),
+    };
+    format!(
+        "{} You get this message because you enabled notifications for this server in the catalog. Reply {} to stop them, or {} to get them again.",
+        problem, OPT_OUT_COMMAND, OPT_IN_COMMAND
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(outcome: CheckOutcome, country: &str) -> StoredStatus {
+        StoredStatus {
+            outcome,
+            country: Some(country.to_string()),
+        }
+    }
+
+    fn status(outcome: CheckOutcome, country: &str) -> ServerStatus {
+        ServerStatus {
+            outcome,
+            country: Some(country.to_string()),
+            info_page_available: false,
+            server_version: None,
+            protocol_versions: None,
+            outdated: None,
+            hosts: vec![],
+        }
+    }
+
+    #[test]
+    fn test_change_without_debounce() {
+        let events = detect_events(
+            "a",
+            &status(CheckOutcome::Down, "NL"),
+            &[stored(CheckOutcome::Up, "DE")],
+            1,
+        );
+        assert_eq!(
+            events.iter().map(|event| &event.kind).collect::<Vec<_>>(),
+            [
+                &StatusEventKind::WentDown,
+                &StatusEventKind::CountryChanged {
+                    previous: "DE".to_string(),
+                    current: "NL".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_debounce() {
+        let down = status(CheckOutcome::Down, "DE");
+        let up = stored(CheckOutcome::Up, "DE");
+        // first failure is not reported yet
+        assert!(detect_events("a", &down, &[up.clone(), up.clone()], 2).is_empty());
+        // the second one in a row is
+        let events = detect_events(
+            "a",
+            &down,
+            &[stored(CheckOutcome::Down, "DE"), up.clone()],
+            2,
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, StatusEventKind::WentDown);
+        // and only once
+        let history = [
+            stored(CheckOutcome::Down, "DE"),
+            stored(CheckOutcome::Down, "DE"),
+        ];
+        assert!(detect_events("a", &down, &history, 2).is_empty());
+    }
+
+    #[test]
+    fn test_failing_operator_notification() {
+        let down = status(CheckOutcome::Down, "DE");
+        let history = [
+            stored(CheckOutcome::Down, "DE"),
+            stored(CheckOutcome::Down, "DE"),
+            stored(CheckOutcome::Up, "DE"),
+        ];
+        assert_eq!(
+            detect_operator_notifications(&down, &history, 3),
+            [OperatorNotification::Failing { checks: 3 }]
+        );
+        assert!(detect_operator_notifications(&down, &history[1..], 3).is_empty());
+        // a new server that fails from its first check
+        assert_eq!(
+            detect_operator_notifications(&down, &history[..2], 3),
+            [OperatorNotification::Failing { checks: 3 }]
+        );
+        assert!(
+            detect_operator_notifications(&status(CheckOutcome::Up, "DE"), &history, 3).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_failing_operator_notification_is_sent_once() {
+        let down = status(CheckOutcome::Down, "DE");
+        let history = [
+            stored(CheckOutcome::Down, "DE"),
+            stored(CheckOutcome::Down, "DE"),
+            stored(CheckOutcome::Down, "DE"),
+            stored(CheckOutcome::Up, "DE"),
+        ];
+        assert!(detect_operator_notifications(&down, &history, 3).is_empty());
+        assert!(detect_operator_notifications(&down, &history, 1).is_empty());
+    }
+
+    #[test]
+    fn test_recovered_operator_notification() {
+        let up = status(CheckOutcome::Up, "DE");
+        let history = [
+            stored(CheckOutcome::Down, "DE"),
+            stored(CheckOutcome::Down, "DE"),
+            stored(CheckOutcome::Up, "DE"),
+        ];
+        assert_eq!(
+            detect_operator_notifications(&up, &history, 2),
+            [OperatorNotification::Recovered]
+        );
+        // it was down, but not long enough to be failing
+        assert!(detect_operator_notifications(&up, &history, 3).is_empty());
+        assert!(detect_operator_notifications(
+            &status(CheckOutcome::PasswordRequired, "DE"),
+            &history,
+            2
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_unknown_is_no_change() {
+        let events = detect_events(
+            "a",
+            &status(CheckOutcome::Up, "DE"),
+            &[stored(CheckOutcome::PasswordRequired, "DE")],
+            1,
+        );
+        assert!(events.is_empty());
+        assert!(detect_events("a", &status(CheckOutcome::Up, "DE"), &[], 1).is_empty());
+    }
+}
@@ -1,43 +1,517 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fmt;
 use std::future::Future;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Value that must not appear in logs; `Debug` prints a placeholder instead
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckOutcome {
+    Up,
+    Down,
+    /// The server only accepts clients that know its password, and we don't have a valid one
+    PasswordRequired,
+    /// The catalog entry is not a valid server address, so it was not tested
+    InvalidAddress,
+    /// A host is or resolves to a loopback, private or otherwise non-public address
+    NonPublicAddress,
+    /// A clearnet host could not be resolved, so it can't be vetted before testing
+    UnresolvableAddress,
+}
+
+impl CheckOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckOutcome::Up => "up",
+            CheckOutcome::Down => "down",
+            CheckOutcome::PasswordRequired => "password_required",
+            CheckOutcome::InvalidAddress => "invalid_address",
+            CheckOutcome::NonPublicAddress => "rejected_non_public_address",
+            CheckOutcome::UnresolvableAddress => "unresolvable_address",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            CheckOutcome::Up,
+            CheckOutcome::Down,
+            CheckOutcome::PasswordRequired,
+            CheckOutcome::InvalidAddress,
+            CheckOutcome::NonPublicAddress,
+            CheckOutcome::UnresolvableAddress,
+        ]
+        .into_iter()
+        .find(|outcome| outcome.as_str() == value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    V4,
+    V6,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DnsLookup {
+    pub addresses: Vec<IpAddr>,
+    pub cname_chain: Vec<String>,
+    pub ttl: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DnsStatus {
+    pub resolves: bool,
+    pub has_a: bool,
+    pub has_aaaa: bool,
+    pub cname_chain: Vec<String>,
+    pub ttl: Option<u32>,
+    pub addresses: Vec<IpAddr>,
+    /// Whether the resolved addresses differ from the previous check; `None` if there is nothing to compare with
+    pub changed: Option<bool>,
+}
+
+#[derive(Debug)]
+pub struct GeoLocation {
+    pub country: String,
+    /// Name of the GeoIP source that answered
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkType {
+    Clearnet,
+    Tor,
+    I2p,
+    Yggdrasil,
+}
+
+impl NetworkType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NetworkType::Clearnet => "clearnet",
+            NetworkType::Tor => "tor",
+            NetworkType::I2p => "i2p",
+            NetworkType::Yggdrasil => "yggdrasil",
+        }
+    }
+}
+
+/// Details published on the info page a SimpleX server generates from its configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InfoPage {
+    pub server_name: Option<String>,
+    pub operator: Option<String>,
+    /// Email addresses and SimpleX contact links of the administrator
+    pub contacts: Vec<String>,
+    pub source_code: Option<String>,
+    pub server_version: Option<String>,
+    pub hosting_country: Option<String>,
+    pub usage_conditions: Option<String>,
+    /// None of the operator details were filled in
+    pub is_stock_template: bool,
+}
+
+/// Whether the body of an info page response was read, or why it was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyOutcome {
+    Read,
+    /// Larger than the configured limit; reading stopped there
+    Oversized,
+    /// Content type outside the allowlist, so the body was not read
+    UnsafeContentType,
+    /// Compressed although we never asked for it, so the body was not read
+    UnsafeEncoding,
+}
+
+impl BodyOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BodyOutcome::Read => "read",
+            BodyOutcome::Oversized => "oversized",
+            BodyOutcome::UnsafeContentType => "unsafe_content_type",
+            BodyOutcome::UnsafeEncoding => "unsafe_encoding",
+        }
+    }
+}
+
+/// Last response received while probing a host for its info page
+#[derive(Debug, Clone)]
+pub struct InfoPageResponse {
+    /// URL after following redirects
+    pub final_url: String,
+    pub status_code: u16,
+    pub content_type: Option<String>,
+    pub body: BodyOutcome,
+    /// Redirected to another host that doesn't serve a SimpleX page
+    pub redirected_away: bool,
+    /// `None` if the response isn't a SimpleX server page
+    pub page: Option<InfoPage>,
+}
+
+/// What a host serves over HTTP; the certificate is read from the connection the info page
+/// was fetched on where possible
+#[derive(Debug, Clone, Default)]
+pub struct HttpStatus {
+    /// `None` if no configured scheme got a response
+    pub info_page: Option<InfoPageResponse>,
+    /// `None` for hosts that don't serve HTTPS
+    pub tls_certificate: Option<TlsCertificate>,
+}
+
+/// Leaf certificate presented by a clearnet host over HTTPS
+#[derive(Debug, Clone)]
+pub struct TlsCertificate {
+    pub issuer: String,
+    pub subject: String,
+    pub subject_alt_names: Vec<String>,
+    pub not_after: DateTime<Utc>,
+    /// The chain verifies against the system roots and the certificate covers the host
+    pub chain_valid: bool,
+    pub hostname_matches: bool,
+    /// Expires within the configured warning period, or has already expired
+    pub expires_soon: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    Open,
+    /// The host answered but refused the connection
+    Closed,
+    /// No answer within the timeout, or the network reported the host unreachable
+    Filtered,
+}
+
+impl PortState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+        }
+    }
+}
+
+/// Protocol versions a server supports, as announced in its handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+/// Result of a plain TCP connection to the server port, independent of the protocol test
+#[derive(Debug, Clone)]
+pub struct TransportStatus {
+    pub port: u16,
+    pub state: PortState,
+    /// Time to establish the connection; only known for open ports
+    pub connect_time: Option<Duration>,
+    /// Read from the handshake of SMP servers whose port is open; XFTP servers only send theirs
+    /// in response to an HTTP/2 request and are not probed beyond the port state
+    pub protocol_versions: Option<VersionRange>,
+}
+
+#[derive(Debug)]
+pub struct HostStatus {
+    pub host: String,
+    pub network: NetworkType,
+    /// Country of the host itself; unknown for onion, I2P and Yggdrasil hosts
+    pub country: Option<String>,
+    pub country_source: Option<String>,
+    /// `None` if the host didn't respond over HTTP at all
+    pub info_page: Option<InfoPageResponse>,
+    /// `None` for hosts not reached over HTTPS or that didn't complete a TLS handshake
+    pub tls_certificate: Option<TlsCertificate>,
+    /// `None` if the port could not be probed from here, as for I2P hosts
+    pub transport: Option<TransportStatus>,
+    /// Outcome when the server is addressed by this host only
+    pub outcome: CheckOutcome,
+    pub dns: Option<DnsStatus>,
+    /// Status when connecting over IPv4 only; `None` if the host has no IPv4 address or is not on clearnet
+    pub ipv4_status: Option<bool>,
+    /// Status when connecting over IPv6 only; `None` if the host has no IPv6 address or is not on clearnet
+    pub ipv6_status: Option<bool>,
+}
 
 #[derive(Debug)]
 pub struct ServerStatus {
+    /// Outcome when the server is addressed by all of its hosts at once
+    pub outcome: CheckOutcome,
+    /// Country of the first clearnet host of the server, or of another server with the same
+    /// identity if the server has no clearnet hosts
     pub country: Option<String>,
     pub info_page_available: bool,
-    pub status: bool,
+    /// Software version published on the info page of the first host that has one
+    pub server_version: Option<String>,
+    /// SMP protocol versions offered by the first host that completed a handshake
+    pub protocol_versions: Option<VersionRange>,
+    /// Whether a known version is below the configured minimum; `None` if no minimum is
+    /// configured or no version is known
+    pub outdated: Option<bool>,
+    pub hosts: Vec<HostStatus>,
 }
 
-#[derive(Debug)]
+/// Result of the previous check of a server, as stored in the repository
+#[derive(Debug, Clone)]
+pub struct StoredStatus {
+    pub outcome: CheckOutcome,
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatusEventKind {
+    WentDown,
+    WentUp,
+    CountryChanged { previous: String, current: String },
+}
+
+impl StatusEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatusEventKind::WentDown => "server_down",
+            StatusEventKind::WentUp => "server_up",
+            StatusEventKind::CountryChanged { .. } => "country_changed",
+        }
+    }
+}
+
+/// Change of a server's status that held long enough to be reported
+#[derive(Debug, Clone)]
+pub struct StatusEvent {
+    pub server_id: String,
+    pub kind: StatusEventKind,
+}
+
+/// Problem of a server its operator is told about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperatorNotification {
+    /// Down in this many consecutive checks
+    Failing { checks: usize },
+    /// Up again after it was failing
+    Recovered,
+    CertificateExpiring {
+        host: String,
+        not_after: DateTime<Utc>,
+    },
+}
+
+impl OperatorNotification {
+    /// Notifications of the same kind share a rate limit
+    pub fn kind(&self) -> &'static str {
+        match self {
+            OperatorNotification::Failing { .. } => "failing",
+            OperatorNotification::Recovered => "recovered",
+            OperatorNotification::CertificateExpiring { .. } => "certificate_expiring",
+        }
+    }
+}
+
+/// Rate-limited like a notification, so a declined request is not repeated every run
+pub const CONTACT_REQUEST_KIND: &str = "contact_request";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageDelivery {
+    Sent,
+    /// Not connected to the contact yet; a connection request was sent
+    ContactRequested,
+    /// Not connected to the contact yet and no request was sent, because an earlier one is
+    /// still waiting for them to accept it or a new one is not allowed yet
+    ContactPending,
+    /// The operator replied with the opt-out command, so nothing was sent
+    OptedOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ServerType {
     SMP,
     XFTP,
 }
 
+impl ServerType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerType::SMP => "smp",
+            ServerType::XFTP => "xftp",
+        }
+    }
+
+    /// Port the clients connect to when the server address doesn't specify one
+    pub fn default_port(&self) -> u16 {
+        match self {
+            ServerType::SMP => 5223,
+            ServerType::XFTP => 443,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Server {
     pub type_: ServerType,
     pub id: String,
     pub identity: String,
-    pub host: String,
+    pub password: Option<Secret>,
+    pub hosts: Vec<String>,
+    /// SimpleX contact address of the operator; only set if they opted in to notifications
+    pub operator_contact: Option<String>,
 }
 
 pub trait ServerCheckerPort {
-    fn check_server(&self, url: &str) -> impl Future<Output = Option<bool>>;
+    fn check_server(&self, url: &str) -> impl Future<Output = Option<CheckOutcome>>;
 }
 
 pub trait HttpCheckerPort {
-    fn is_page_available(&self, host: &str) -> impl Future<Output = bool>;
+    fn check_http(&self, host: &str) -> impl Future<Output = HttpStatus>;
+}
+
+pub trait TransportCheckerPort {
+    /// Connects to the port of the host, or to the default port of the server type if the host
+    /// doesn't specify one. Tor hosts are reached through the Tor proxy.
+    fn check_transport(
+        &self,
+        host: &str,
+        type_: ServerType,
+    ) -> impl Future<Output = Option<TransportStatus>>;
+}
+
+/// Futures are `Send` so HTTP clients can resolve through the port from any task
+pub trait ResolverPort {
+    fn resolve(&self, host: &str) -> impl Future<Output = Option<Vec<IpAddr>>> + Send;
+    fn lookup(
+        &self,
+        host: &str,
+        family: AddressFamily,
+    ) -> impl Future<Output = Option<DnsLookup>> + Send;
+}
+
+impl<T: ResolverPort> ResolverPort for Arc<T> {
+    fn resolve(&self, host: &str) -> impl Future<Output = Option<Vec<IpAddr>>> + Send {
+        self.as_ref().resolve(host)
+    }
+
+    fn lookup(
+        &self,
+        host: &str,
+        family: AddressFamily,
+    ) -> impl Future<Output = Option<DnsLookup>> + Send {
+        self.as_ref().lookup(host, family)
+    }
+}
+
+pub trait DnsCheckerPort {
+    /// Returns `None` for hosts that are not resolved through DNS (IP literals, onion, I2P, Yggdrasil)
+    /// and if a lookup failed without an answer from the name server
+    fn check_dns(&self, host: &str) -> impl Future<Output = Option<DnsStatus>>;
 }
 
 pub trait GeoIpPort {
-    fn get_country(&self, host: &str) -> impl Future<Output = Option<String>>;
+    /// Refreshes outdated databases; returns `None` if one is stale and refused to be used
+    fn ensure_fresh(&self) -> impl Future<Output = Option<()>>;
+    /// Returns `None` for hosts that cannot be geolocated, including onion, I2P and Yggdrasil hosts
+    fn get_country(&self, host: &str) -> impl Future<Output = Option<GeoLocation>>;
+}
+
+pub trait NotifierPort {
+    /// Returns `None` if the event could not be delivered everywhere
+    fn notify(&self, event: &StatusEvent) -> impl Future<Output = Option<()>>;
+}
+
+pub trait OperatorMessengerPort {
+    /// Sends a SimpleX message to the contact address unless the operator opted out, or asks to
+    /// connect to it first if `may_request_contact`
+    fn send_message(
+        &self,
+        contact_address: &str,
+        text: &str,
+        may_request_contact: bool,
+    ) -> impl Future<Output = Option<MessageDelivery>>;
+}
+
+/// Observability of the validator itself; recording must not fail or block for long
+pub trait MetricsPort {
+    fn record_check(&self, network: NetworkType, outcome: CheckOutcome);
+    fn record_check_duration(&self, duration: Duration);
+    fn record_retry(&self);
+    fn record_repository_write_failure(&self);
+    /// A clearnet host that no GeoIP source could locate
+    fn record_geoip_miss(&self);
+    /// A run is successful if the servers could be retrieved
+    fn record_run(&self, duration: Duration, success: bool);
+}
+
+impl<T: MetricsPort> MetricsPort for Arc<T> {
+    fn record_check(&self, network: NetworkType, outcome: CheckOutcome) {
+        self.as_ref().record_check(network, outcome)
+    }
+
+    fn record_check_duration(&self, duration: Duration) {
+        self.as_ref().record_check_duration(duration)
+    }
+
+    fn record_retry(&self) {
+        self.as_ref().record_retry()
+    }
+
+    fn record_repository_write_failure(&self) {
+        self.as_ref().record_repository_write_failure()
+    }
+
+    fn record_geoip_miss(&self) {
+        self.as_ref().record_geoip_miss()
+    }
+
+    fn record_run(&self, duration: Duration, success: bool) {
+        self.as_ref().record_run(duration, success)
+    }
 }
 
 pub trait ServerRepositoryPort {
     fn get_servers(&self) -> impl Future<Output = Option<Vec<Server>>>;
+    /// Up to `count` stored statuses of the server, most recent first
+    fn get_recent_statuses(
+        &self,
+        server_id: &str,
+        count: usize,
+    ) -> impl Future<Output = Option<Vec<StoredStatus>>>;
+    /// When the operator of the server last got a notification of this kind
+    fn get_last_operator_notification(
+        &self,
+        server_id: &str,
+        kind: &str,
+    ) -> impl Future<Output = Option<DateTime<Utc>>>;
+    fn record_operator_notification(
+        &self,
+        server_id: &str,
+        kind: &str,
+    ) -> impl Future<Output = Option<()>>;
+    fn get_last_dns_addresses(
+        &self,
+        server_id: &str,
+        host: &str,
+    ) -> impl Future<Output = Option<Vec<IpAddr>>>;
     fn update_server_status(
         &self,
-        server_id: &String,
+        server_id: &str,
         status: &ServerStatus,
     ) -> impl Future<Output = Option<()>>;
 }
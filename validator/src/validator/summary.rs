@@ -0,0 +1,294 @@
+use super::ports::{CheckOutcome, Server, ServerStatus};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Number of servers listed as the slowest of a run
+const SLOWEST_COUNT: usize = 10;
+
+/// Servers that responded but required a password we don't have count as unknown, as do
+/// servers whose check failed
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    pub checked: u32,
+    pub up: u32,
+    pub down: u32,
+    pub unknown: u32,
+    /// Not tested because of an invalid, non-public or unresolvable address
+    pub skipped: u32,
+}
+
+impl Totals {
+    /// `None` stands for a check that failed before producing an outcome
+    fn add(&mut self, outcome: Option<CheckOutcome>) {
+        match outcome {
+            Some(CheckOutcome::Up) => {
+                self.checked += 1;
+                self.up += 1;
+            }
+            Some(CheckOutcome::Down) => {
+                self.checked += 1;
+                self.down += 1;
+            }
+            Some(CheckOutcome::PasswordRequired) => {
+                self.checked += 1;
+                self.unknown += 1;
+            }
+            Some(CheckOutcome::InvalidAddress)
+            | Some(CheckOutcome::NonPublicAddress)
+            | Some(CheckOutcome::UnresolvableAddress) => {
+                self.skipped += 1;
+            }
+            None => self.unknown += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowServer {
+    pub server_id: String,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusChange {
+    pub server_id: String,
+    pub previous: &'static str,
+    pub current: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckError {
+    /// `None` for errors that aren't about a single server
+    pub server_id: Option<String>,
+    pub error: String,
+}
+
+/// What happened during one run of `App::check_servers`
+#[derive(Debug, Default, Serialize)]
+pub struct RunSummary {
+    pub duration_seconds: f64,
+    pub totals: Totals,
+    pub by_server_type: BTreeMap<&'static str, Totals>,
+    /// Outcomes of the individual hosts by their network type
+    pub by_network: BTreeMap<&'static str, Totals>,
+    pub slowest: Vec<SlowServer>,
+    /// Servers whose outcome differs from their previous check
+    pub changes: Vec<StatusChange>,
+    pub errors: Vec<CheckError>,
+}
+
+impl RunSummary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_server(&mut self, server: &Server, outcome: Option<CheckOutcome>, duration: Duration) {
+        self.totals.add(outcome);
+        self.by_server_type
+            .entry(server.type_.as_str())
+            .or_default()
+            .add(outcome);
+        self.slowest.push(SlowServer {
+            server_id: server.id.clone(),
+            seconds: duration.as_secs_f64(),
+        });
+    }
+
+    pub fn record_status(
+        &mut self,
+        server: &Server,
+        status: &ServerStatus,
+        previous: Option<CheckOutcome>,
+        duration: Duration,
+    ) {
+        self.add_server(server, Some(status.outcome), duration);
+        for host in &status.hosts {
+            self.by_network
+                .entry(host.network.as_str())
+                .or_default()
+                .add(Some(host.outcome));
+        }
+        if let Some(previous) = previous.filter(|previous| *previous != status.outcome) {
+            self.changes.push(StatusChange {
+                server_id: server.id.clone(),
+                previous: previous.as_str(),
+                current: status.outcome.as_str(),
+            });
+        }
+    }
+
+    pub fn record_error(&mut self, server: &Server, error: String, duration: Duration) {
+        self.add_server(server, None, duration);
+        self.errors.push(CheckError {
+            server_id: Some(server.id.clone()),
+            error,
+        });
+    }
+
+    /// An error about a server whose check itself finished, e.g. its status was not stored
+    pub fn record_server_error(&mut self, server: &Server, error: String) {
+        self.errors.push(CheckError {
+            server_id: Some(server.id.clone()),
+            error,
+        });
+    }
+
+    pub fn record_run_error(&mut self, error: String) {
+        self.errors.push(CheckError {
+            server_id: None,
+            error,
+        });
+    }
+
+    /// Keeps only the slowest servers
+    pub fn finish(&mut self, duration: Duration) {
+        self.duration_seconds = duration.as_secs_f64();
+        self.slowest.sort_by(|a, b| b.seconds.total_cmp(&a.seconds));
+        self.slowest.truncate(SLOWEST_COUNT);
+    }
+
+    /// Formatted for the GitHub Actions job summary
+    pub fn to_markdown(&self) -> String {
+        let mut output = String::new();
+        let _ = writeln!(output, "## Server check summary\n");
+        let _ = writeln!(output, "Finished in {:.0}s.\n", self.duration_seconds);
+
+        let _ = writeln!(output, "| | Checked | Up | Down | Unknown | Skipped |");
+        let _ = writeln!(output, "|---|---:|---:|---:|---:|---:|");
+        let rows = std::iter::once(("**All servers**".to_string(), &self.totals))
+            .chain(
+                self.by_server_type
+                    .iter()
+                    .map(|(type_, totals)| (type_.to_uppercase(), totals)),
+            )
+            .chain(
+                self.by_network
+                    .iter()
+                    .map(|(network, totals)| (format!("Hosts on {}", network), totals)),
+            );
+        for (name, totals) in rows {
+            let _ = writeln!(
+                output,
+                "| {} | {} | {} | {} | {} | {} |",
+                name, totals.checked, totals.up, totals.down, totals.unknown, totals.skipped
+            );
+        }
+
+        if !self.changes.is_empty() {
+            let _ = writeln!(output, "\n### Status changes\n");
+            for change in &self.changes {
+                let _ = writeln!(
+                    output,
+                    "- `{}`: {} → {}",
+                    change.server_id, change.previous, change.current
+                );
+            }
+        }
+
+        if !self.slowest.is_empty() {
+            let _ = writeln!(output, "\n### Slowest servers\n");
+            let _ = writeln!(output, "| Server | Seconds |");
+            let _ = writeln!(output, "|---|---:|");
+            for server in &self.slowest {
+                let _ = writeln!(output, "| `{}` | {:.1} |", server.server_id, server.seconds);
+            }
+        }
+
+        if !self.errors.is_empty() {
+            let _ = writeln!(output, "\n### Errors\n");
+            for error in &self.errors {
+                match &error.server_id {
+                    Some(server_id) => {
+                        let _ = writeln!(output, "- `{}`: {}", server_id, error.error);
+                    }
+                    None => {
+                        let _ = writeln!(output, "- {}", error.error);
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator::ports::ServerType;
+
+    fn server(id: &str, type_: ServerType) -> Server {
+        Server {
+            type_,
+            id: id.to_string(),
+            identity: String::new(),
+            password: None,
+            hosts: vec![],
+            operator_contact: None,
+        }
+    }
+
+    fn status(outcome: CheckOutcome) -> ServerStatus {
+        ServerStatus {
+            outcome,
+            country: None,
+            info_page_available: false,
+            server_version: None,
+            protocol_versions: None,
+            outdated: None,
+            hosts: vec![],
+        }
+    }
+
+    #[test]
+    fn test_totals() {
+        let mut summary = RunSummary::new();
+        let smp = server("a", ServerType::SMP);
+        let xftp = server("b", ServerType::XFTP);
+        summary.record_status(&smp, &status(CheckOutcome::Up), None, Duration::ZERO);
+        summary.record_status(
+            &xftp,
+            &status(CheckOutcome::InvalidAddress),
+            None,
+            Duration::ZERO,
+        );
+        summary.record_error(&smp, "Failed to check server".to_string(), Duration::ZERO);
+        summary.record_server_error(&xftp, "Failed to store the server status".to_string());
+        assert_eq!(summary.errors.len(), 2);
+        assert_eq!(
+            summary.totals,
+            Totals {
+                checked: 1,
+                up: 1,
+                down: 0,
+                unknown: 1,
+                skipped: 1
+            }
+        );
+        assert_eq!(summary.by_server_type["smp"].unknown, 1);
+        assert_eq!(summary.by_server_type["xftp"].skipped, 1);
+    }
+
+    #[test]
+    fn test_changes_and_slowest() {
+        let mut summary = RunSummary::new();
+        for (index, id) in ["a", "b", "c"].iter().enumerate() {
+            summary.record_status(
+                &server(id, ServerType::SMP),
+                &status(CheckOutcome::Down),
+                Some(if index == 0 {
+                    CheckOutcome::Up
+                } else {
+                    CheckOutcome::Down
+                }),
+                Duration::from_secs(index as u64),
+            );
+        }
+        summary.finish(Duration::from_secs(3));
+        assert_eq!(summary.changes.len(), 1);
+        assert_eq!(summary.changes[0].server_id, "a");
+        assert_eq!(summary.slowest[0].server_id, "c");
+        assert!(summary.to_markdown().contains("- `a`: up → down\n"));
+    }
+}
@@ -0,0 +1,221 @@
+use super::ports::VersionRange;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Dotted release number of the server software, e.g. `6.3.4.0` or `6.4.0-beta.3`. Missing
+/// trailing components compare as zero, so `6.3` equals `6.3.0.0`, and a pre-release comes
+/// before its release. Build metadata after `+` is ignored.
+#[derive(Debug, Clone)]
+pub struct SoftwareVersion {
+    components: Vec<u32>,
+    pre_release: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidVersion(pub String);
+
+impl fmt::Display for InvalidVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid version \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for InvalidVersion {}
+
+impl FromStr for SoftwareVersion {
+    type Err = InvalidVersion;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidVersion(version.to_string());
+        let trimmed = version.trim().trim_start_matches(['v', 'V']);
+        let trimmed = trimmed
+            .split_once('+')
+            .map_or(trimmed, |(version, _)| version);
+        let (release, pre_release) = match trimmed.split_once('-') {
+            Some((release, pre_release)) => (release, Some(pre_release)),
+            None => (trimmed, None),
+        };
+        let components = release
+            .split('.')
+            .map(|component| component.parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| invalid())?;
+        if pre_release.is_some_and(|pre_release| {
+            pre_release.split('.').any(|identifier| {
+                identifier.is_empty()
+                    || !identifier
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            })
+        }) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            components,
+            pre_release: pre_release.map(str::to_string),
+        })
+    }
+}
+
+impl fmt::Display for SoftwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let components: Vec<String> = self.components.iter().map(u32::to_string).collect();
+        write!(f, "{}", components.join("."))?;
+        if let Some(pre_release) = &self.pre_release {
+            write!(f, "-{}", pre_release)?;
+        }
+        Ok(())
+    }
+}
+
+impl SoftwareVersion {
+    fn component(&self, index: usize) -> u32 {
+        self.components.get(index).copied().unwrap_or_default()
+    }
+}
+
+/// Numeric identifiers compare as numbers and before alphanumeric ones, as in SemVer, so
+/// `beta.3` comes before `beta.10` and `rc`
+fn compare_pre_releases(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+impl Ord for SoftwareVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (0..self.components.len().max(other.components.len()))
+            .map(|index| self.component(index).cmp(&other.component(index)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| match (&self.pre_release, &other.pre_release) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => compare_pre_releases(a, b),
+            })
+    }
+}
+
+impl PartialOrd for SoftwareVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SoftwareVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for SoftwareVersion {}
+
+/// Versions below which servers are flagged as outdated. Only SMP servers announce their
+/// protocol versions in a handshake the validator reads, so XFTP servers are judged by their
+/// software version alone.
+#[derive(Debug, Clone, Default)]
+pub struct MinimumVersions {
+    pub server_version: Option<SoftwareVersion>,
+    pub smp_version: Option<u16>,
+}
+
+impl MinimumVersions {
+    /// A server is outdated if any version we know of is below its minimum; `None` if no
+    /// version with a configured minimum is known
+    pub fn is_outdated(
+        &self,
+        server_version: Option<&str>,
+        protocol_versions: Option<VersionRange>,
+    ) -> Option<bool> {
+        let software = self
+            .server_version
+            .as_ref()
+            .zip(server_version.and_then(|version| version.parse::<SoftwareVersion>().ok()))
+            .map(|(min, version)| version < *min);
+        let protocol = self
+            .smp_version
+            .zip(protocol_versions)
+            .map(|(min, versions)| versions.max < min);
+        match (software, protocol) {
+            (None, None) => None,
+            (software, protocol) => Some(software.unwrap_or(false) || protocol.unwrap_or(false)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> SoftwareVersion {
+        version.parse().expect("valid version")
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(version("v6.3.4.0").to_string(), "6.3.4.0");
+        assert_eq!(version("6.4.0-beta.3").to_string(), "6.4.0-beta.3");
+        assert_eq!(version("6.4.0-rc.1+abc123").to_string(), "6.4.0-rc.1");
+        assert!("6.3-".parse::<SoftwareVersion>().is_err());
+        assert!("6.3-beta..1".parse::<SoftwareVersion>().is_err());
+        assert!("6.3 beta".parse::<SoftwareVersion>().is_err());
+        assert!("".parse::<SoftwareVersion>().is_err());
+    }
+
+    #[test]
+    fn test_compare_pre_releases() {
+        assert!(version("6.4.0-beta.3") < version("6.4.0"));
+        assert!(version("6.4.0-beta.3") < version("6.4"));
+        assert!(version("6.4.0-beta.3") > version("6.3.9"));
+        assert!(version("6.4.0-beta.3") < version("6.4.0-beta.10"));
+        assert!(version("6.4.0-beta.10") < version("6.4.0-rc.1"));
+        assert!(version("6.4.0-beta") < version("6.4.0-beta.1"));
+        assert_eq!(version("6.4-beta.3"), version("6.4.0-beta.3+build"));
+    }
+
+    #[test]
+    fn test_outdated() {
+        let minimum = MinimumVersions {
+            server_version: Some(version("6.3.0")),
+            smp_version: Some(12),
+        };
+        let range = |min, max| Some(VersionRange { min, max });
+        assert_eq!(minimum.is_outdated(None, None), None);
+        assert_eq!(minimum.is_outdated(Some("6.3.4"), None), Some(false));
+        assert_eq!(minimum.is_outdated(Some("6.2.9"), None), Some(true));
+        assert_eq!(minimum.is_outdated(Some("6.3.0-beta.1"), None), Some(true));
+        assert_eq!(minimum.is_outdated(None, range(6, 14)), Some(false));
+        assert_eq!(minimum.is_outdated(None, range(6, 11)), Some(true));
+        assert_eq!(minimum.is_outdated(Some("6.3.4"), range(6, 11)), Some(true));
+        assert_eq!(minimum.is_outdated(Some("6.2.0"), range(6, 14)), Some(true));
+        // an unparseable version is unknown, not outdated
+        assert_eq!(minimum.is_outdated(Some("unknown"), None), None);
+        assert_eq!(
+            MinimumVersions::default().is_outdated(Some("1.0"), range(1, 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        assert!(version("6.3.4") < version("6.4"));
+        assert!(version("6.10") > version("6.9.9"));
+        assert_eq!(version("6.3"), version("6.3.0.0"));
+    }
+}